@@ -7,6 +7,8 @@
 #![feature(stmt_expr_attributes)]
 
 use core::error::Error;
+use std::os::fd::AsFd;
+use std::os::fd::AsRawFd;
 use wayland_client::Connection;
 
 type Result<T = (), E = Box<dyn Error + Send + Sync + 'static>> = core::result::Result<T, E>;
@@ -14,22 +16,103 @@ type Result<T = (), E = Box<dyn Error + Send + Sync + 'static>> = core::result::
 // --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
 
 slint::slint! {
+    // an output of a module, `state` is for the theme such as the alert of batteries
+    export struct Segment {
+        text: string,
+        state: string,
+    }
+
+    component SegmentText inherits Text {
+        in property<Segment> segment;
+        in property<color> foreground;
+
+        color: segment.state == "critical" ? #ff5555
+            : segment.state == "warning" ? #ffb86c
+            : segment.state == "charging" ? #50fa7b
+            : foreground;
+        font-size: 1.5rem;
+        text: segment.text;
+    }
+
     export component Main inherits Window {
-        background: transparent;
+        in property<string> font-family: "0xProto";
+        in property<int> font-weight: 100;
+        in property<color> foreground: #ffffff;
+        in property<color> background-color: transparent;
 
-        default-font-family: "0xProto";
-        default-font-weight: 100;
+        background: background-color;
 
+        default-font-family: font-family;
+        default-font-weight: font-weight;
+
+        // combined over all batteries, and each of them labeled by its name
         in property<string> battery-level;
+        in property<[string]> batteries;
+        // one of charging, discharging, full, not-charging and unknown
+        in property<string> battery-status;
+        in property<string> battery-power;
+        in property<string> battery-eta;
+        // one of normal, warning and critical
+        in property<string> battery-alert;
+        // any of ac adapters or usb power supplies is plugged in
+        in property<bool> ac-online;
+
+        // the local time, and each of extra time zones labeled by its name
+        in property<string> clock;
+        in property<[string]> clocks;
+
+        // populated by the modules in the order of the config
+        in property<[Segment]> left;
+        // not `center`, which would shadow the value of alignments
+        in property<[Segment]> middle;
+        in property<[Segment]> right;
+
+        // placed on the left or right edge
+        in property<bool> vertical;
+
+        if !vertical: HorizontalLayout {
+            HorizontalLayout {
+                alignment: start;
+                spacing: 8px;
+
+                for s in left: SegmentText { segment: s; foreground: foreground; }
+            }
 
-        GridLayout {
-            Row {
-                Rectangle {
-                    height: 100%;
-                    border-radius: 4px;
+            HorizontalLayout {
+                alignment: center;
+                spacing: 8px;
 
-                    Text { color: #ffffff; font-size: 1.5rem; text: battery-level; }
-                }
+                for s in middle: SegmentText { segment: s; foreground: foreground; }
+            }
+
+            HorizontalLayout {
+                alignment: end;
+                spacing: 8px;
+
+                for s in right: SegmentText { segment: s; foreground: foreground; }
+            }
+        }
+
+        if vertical: VerticalLayout {
+            VerticalLayout {
+                alignment: start;
+                spacing: 8px;
+
+                for s in left: SegmentText { segment: s; foreground: foreground; horizontal-alignment: center; }
+            }
+
+            VerticalLayout {
+                alignment: center;
+                spacing: 8px;
+
+                for s in middle: SegmentText { segment: s; foreground: foreground; horizontal-alignment: center; }
+            }
+
+            VerticalLayout {
+                alignment: end;
+                spacing: 8px;
+
+                for s in right: SegmentText { segment: s; foreground: foreground; horizontal-alignment: center; }
             }
         }
     }
@@ -38,693 +121,3660 @@ slint::slint! {
 // --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
 
 fn main() -> Result {
-    let connection = Connection::connect_to_env()?;
-    let display = connection.display();
+    let factory = init_platform()?;
+    let event_loop = EventLoop::new()?;
 
-    let mut queue = connection.new_event_queue();
-    let mut state = InitialGateState::default();
+    let mut config = Config::load()?;
 
-    let _ = display.get_registry(&queue.handle(), ());
+    // reloading is optional, the bar works with the config at startup anyway
+    let watcher = match Config::path().map(|p| ConfigWatcher::new(&p)) {
+        Some(Ok(watcher)) => {
+            event_loop.add(&watcher, Token::Config)?;
+            Some(watcher)
+        }
+        Some(Err(e)) => {
+            eprintln!("the config won't be reloaded: {e}");
+            None
+        }
+        None => None,
+    };
 
-    queue.roundtrip(&mut state)?;
-    queue.roundtrip(&mut state)?;
+    factory.load(config.style.ui.as_deref());
 
-    let mut queue = connection.new_event_queue();
-    let mut state = state.forward(&queue.handle())?;
+    let mut registry = Registry::new(&config, &event_loop)?;
 
-    queue.roundtrip(&mut state)?;
+    let mut session = Session::connect(&factory, &config)?;
+    session.attach(&event_loop, &registry)?;
 
-    let mut state = state.forward(&connection)?;
+    loop {
+        slint::platform::update_timers_and_animations();
 
-    let window = create_window();
-    let ui = Main::new()?;
+        let ready = match session.step(&event_loop, &factory, &registry) {
+            Ok(ready) => ready,
 
-    state.windows.iter().for_each(|(w, _)| {
-        let width = w.mode.width;
-        let height = w.mode.height / 64;
+            Err(e) if is_disconnected(&*e) => {
+                eprintln!("disconnected from the compositor: {e}");
 
-        w.layer_surface.set_size(width as u32, height as u32);
-        w.layer_surface.set_exclusive_zone(height as i32);
-        w.surface.commit();
+                session.detach(&event_loop);
+                drop(session);
 
-        window.set_size(slint::PhysicalSize::new(width as u32, height as u32));
-    });
+                session = Session::reconnect(&factory, &config);
+                session.attach(&event_loop, &registry)?;
 
-    ui.show()?;
+                continue;
+            }
 
-    let mut rbc = Transition::new(read_battery_cap, Duration::from_secs(60));
-    ui.set_battery_level(read_battery_cap());
+            Err(e) => return Err(e),
+        };
 
-    loop {
-        for (w, q) in &mut state.windows {
-            slint::platform::update_timers_and_animations();
+        // ^^^ event loop ^^^
 
-            let pixels = unsafe { w.raw.as_slice_mut::<Pixel>() }?;
+        for token in ready {
+            match token {
+                Token::Wayland => (),
 
-            window.draw_if_needed(|r| {
-                r.render(pixels, w.mode.width);
+                Token::Module(index) => {
+                    if !registry.poll(index)? {
+                        continue;
+                    }
 
-                let conv = w.pp.as_converter();
-                for e in pixels {
-                    *e = conv(*e);
+                    session
+                        .state
+                        .windows
+                        .iter()
+                        .for_each(|(w, _)| registry.apply(&w.view.ui));
                 }
 
-                w.surface.damage(0, 0, i32::MAX, i32::MAX);
-                w.surface.commit();
-            });
+                Token::Config => {
+                    let Some(watcher) = &watcher else {
+                        continue;
+                    };
+
+                    if !watcher.changed()? {
+                        continue;
+                    }
+
+                    let new = match Config::load() {
+                        Ok(new) if new == config => continue,
+                        Ok(new) => new,
+
+                        Err(e) => {
+                            eprintln!("failed to reload the config, keeps the previous one: {e}");
+                            continue;
+                        }
+                    };
+
+                    if new.modules != config.modules
+                        || new.battery != config.battery
+                        || new.clock != config.clock
+                    {
+                        // resumed, or the alerts would be raised again for the same level
+                        registry = registry.reload(&new, &event_loop)?;
+
+                        session
+                            .state
+                            .windows
+                            .iter()
+                            .for_each(|(w, _)| registry.apply(&w.view.ui));
+                    }
+
+                    if new.style.ui != config.style.ui {
+                        factory.load(new.style.ui.as_deref());
+                    }
+
+                    session.apply(&new);
+                    config = new;
+                }
+            }
+        }
+
+        // ^^^ represent ^^^
+    }
+}
+
+// --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
 
-            // ^^^ update ^^^
+use wayland_client::backend::WaylandError;
+use wayland_client::DispatchError;
 
-            q.roundtrip(w)?;
+/// a connection to the compositor, and windows on it
+#[derive(Debug)]
+struct Session {
+    connection: Connection,
+    queue: EventQueue<ReadyGateState>,
+    state: ReadyGateState,
+}
 
-            // ^^^ event loop ^^^
+impl Session {
+    fn connect(factory: &WindowFactory, config: &Config) -> Result<Self> {
+        let connection = Connection::connect_to_env()?;
+        let display = connection.display();
 
-            rbc.update_if_elapsed(|ss| ui.set_battery_level(ss));
+        let mut queue = connection.new_event_queue();
+        let mut state = InitialGateState::default();
 
-            // ^^^ represent ^^^
-        }
+        let _ = display.get_registry(&queue.handle(), ());
+
+        queue.roundtrip(&mut state)?;
+        queue.roundtrip(&mut state)?;
+
+        let mut queue = connection.new_event_queue();
+        let mut state = state.forward(&queue.handle())?;
+
+        queue.roundtrip(&mut state)?;
+
+        let queue = connection.new_event_queue();
+        let state = state.forward(&connection, factory, config, &queue.handle())?;
 
-        std::thread::sleep(Duration::from_secs(1));
+        Ok(Self {
+            connection,
+            queue,
+            state,
+        })
     }
-}
 
-// --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
+    /// retries to connect with exponential backoff until it succeeds
+    fn reconnect(factory: &WindowFactory, config: &Config) -> Self {
+        let mut backoff = Duration::from_millis(100);
 
-fn read_battery_cap() -> slint::SharedString {
-    let Ok(raw) = std::fs::read_to_string("/sys/class/power_supply/macsmc-battery/capacity") else {
-        return slint::format!("");
-    };
+        loop {
+            // the compositor may be still restarting
+            std::thread::sleep(backoff);
 
-    let Ok(num) = raw.trim().parse::<f32>() else {
-        return slint::format!("");
-    };
+            match Self::connect(factory, config) {
+                Ok(session) => return session,
+                Err(e) => eprintln!("failed to reconnect to the compositor: {e}"),
+            }
 
-    slint::format!("{num}%")
-}
+            backoff = (backoff * 2).min(Duration::from_secs(30));
+        }
+    }
 
-// --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
+    fn attach(&self, event_loop: &EventLoop, registry: &Registry) -> Result {
+        event_loop.add(self.connection.backend().poll_fd(), Token::Wayland)?;
 
-use core::time::Duration;
-use std::time::Instant;
+        self.state
+            .windows
+            .iter()
+            .for_each(|(w, _)| registry.apply(&w.view.ui));
 
-struct Transition<F> {
-    update: F,
-    interval: Duration,
-    before: Instant,
-}
+        Ok(())
+    }
 
-impl<F: FnMut() -> slint::SharedString> Transition<F> {
-    pub fn new(update: F, interval: Duration) -> Self {
-        let before = Instant::now();
+    /// applies the reloaded config, the windows are recreated only if the placement or the ui is changed
+    fn apply(&mut self, config: &Config) {
+        let state = &mut self.state;
 
-        Self {
-            update,
-            interval,
-            before,
+        let recreate =
+            state.config.placement != config.placement || state.config.style.ui != config.style.ui;
+
+        for (w, _) in &mut state.windows {
+            config.style.apply(&w.view.ui);
+
+            if recreate {
+                w.closed = true;
+            }
         }
+
+        state.config = config.clone();
+
+        // not left to the next `step`, whose wait may last until any module wakes it up,
+        // and then recreated with the new placement once the outputs are bound again
+        state.reap(&self.queue.handle());
     }
 
-    pub fn update_if_elapsed(&mut self, set: impl FnOnce(slint::SharedString)) {
-        if self.before.elapsed() < self.interval {
-            return;
+    fn detach(&self, event_loop: &EventLoop) {
+        // the fd may be already closed
+        let _ = event_loop.delete(self.connection.backend().poll_fd());
+    }
+
+    /// renders, waits for events, and dispatches them, returns the ready sources
+    fn step(
+        &mut self,
+        event_loop: &EventLoop,
+        factory: &WindowFactory,
+        registry: &Registry,
+    ) -> Result<Vec<Token>> {
+        let Self {
+            connection,
+            queue,
+            state,
+        } = self;
+
+        for (w, q) in &mut state.windows {
+            w.render(&q.handle())?;
+        }
+
+        // ^^^ update ^^^
+
+        // slint timers are woken up by the timeout, not by any fd
+        let timeout = slint::platform::duration_until_next_timer_update();
+        let ready = event_loop.wait(connection, timeout)?;
+
+        queue.dispatch_pending(state)?;
+
+        for (w, q) in &mut state.windows {
+            q.dispatch_pending(w)?;
+            w.reconfigure(connection, &q.handle())?;
+        }
+
+        state.reap(&queue.handle());
+
+        if state.spawn(connection, factory)? {
+            state
+                .windows
+                .iter()
+                .for_each(|(w, _)| registry.apply(&w.view.ui));
         }
 
-        set((self.update)());
-        self.before = Instant::now();
+        Ok(ready)
     }
 }
 
+/// whether the error is caused by the connection to the compositor
+fn is_disconnected(e: &(dyn Error + Send + Sync + 'static)) -> bool {
+    e.is::<WaylandError>() || matches!(e.downcast_ref(), Some(DispatchError::Backend(_)))
+}
+
 // --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
 
-use std::rc::Rc;
+use std::path::Path;
+
+/// represented batteries, empty if no battery is found
+#[derive(Debug, Clone, Default, PartialEq)]
+struct BatteryState {
+    combined: slint::SharedString,
+    each: Vec<slint::SharedString>,
+    status: Status,
+    /// drawn or charged in watts
+    power: slint::SharedString,
+    /// until empty while discharging, or until full while charging
+    eta: slint::SharedString,
+    alert: Alert,
+    ac_online: bool,
+}
 
-use slint::platform::software_renderer::MinimalSoftwareWindow;
-use slint::platform::WindowAdapter;
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+enum Alert {
+    #[default]
+    Normal,
+    Warning,
+    Critical,
+}
 
-fn create_window() -> Rc<MinimalSoftwareWindow> {
-    let window = MinimalSoftwareWindow::new(Default::default());
-    let platform = Platform {
-        window: window.clone(),
-    };
+impl Alert {
+    /// for the theme of the ui
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Normal => "normal",
+            Self::Warning => "warning",
+            Self::Critical => "critical",
+        }
+    }
 
-    slint::platform::set_platform(Box::new(platform)).unwrap();
+    /// enters at the thresholds, but leaves only above the thresholds with the hysteresis
+    fn next(self, level: f32, config: &AlertConfig) -> Self {
+        let classify = |margin: u8| {
+            if level <= config.critical as f32 + margin as f32 {
+                Self::Critical
+            } else if level <= config.warning as f32 + margin as f32 {
+                Self::Warning
+            } else {
+                Self::Normal
+            }
+        };
+
+        let entered = classify(0);
+        let left = classify(config.hysteresis);
+
+        match (entered > self, left < self) {
+            (true, _) => entered,
+            (_, true) => left,
+            _ => self,
+        }
+    }
 
-    window
+    fn command(self, config: &AlertConfig) -> Option<&str> {
+        match self {
+            Self::Normal => None,
+            Self::Warning => config.warning_command.as_deref(),
+            Self::Critical => config.critical_command.as_deref(),
+        }
+    }
 }
 
-struct Platform {
-    window: Rc<MinimalSoftwareWindow>,
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum Status {
+    Charging,
+    Discharging,
+    Full,
+    NotCharging,
+    #[default]
+    Unknown,
 }
 
-impl slint::platform::Platform for Platform {
-    fn create_window_adapter(&self) -> Result<Rc<dyn WindowAdapter>, slint::PlatformError> {
-        Ok(self.window.clone())
+impl Status {
+    fn parse(raw: &str) -> Self {
+        match raw {
+            "Charging" => Self::Charging,
+            "Discharging" => Self::Discharging,
+            "Full" => Self::Full,
+            "Not charging" => Self::NotCharging,
+            _ => Self::Unknown,
+        }
     }
 
-    fn duration_since_start(&self) -> core::time::Duration {
-        std::time::SystemTime::now().elapsed().unwrap()
+    /// for the icon of the ui
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Charging => "charging",
+            Self::Discharging => "discharging",
+            Self::Full => "full",
+            Self::NotCharging => "not-charging",
+            Self::Unknown => "unknown",
+        }
     }
-}
 
-// --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
+    /// charging wins over discharging, and full needs all of batteries to be full
+    fn combine(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Charging, _) | (_, Self::Charging) => Self::Charging,
+            (Self::Discharging, _) | (_, Self::Discharging) => Self::Discharging,
+            (Self::Full, Self::Full) => Self::Full,
+            (Self::Unknown, v) | (v, Self::Unknown) => v,
+            _ => Self::NotCharging,
+        }
+    }
+}
 
+/// a `power_supply` device of `type == Battery`
 #[derive(Debug)]
-struct MissingError(String);
+struct Battery {
+    name: String,
+    /// in percent
+    capacity: f32,
+    status: Status,
+    /// in watt-hours, converted from `charge_*` with `voltage_now` if `energy_*` is absent
+    energy: Option<f32>,
+    energy_full: Option<f32>,
+    /// in watts, converted from `current_now` with `voltage_now` if `power_now` is absent
+    power: Option<f32>,
+}
 
-impl MissingError {
-    fn new(content: impl Into<String>) -> Self {
-        Self(content.into())
+impl Battery {
+    fn read(dir: &Path) -> Option<Self> {
+        let read = |attr: &str| std::fs::read_to_string(dir.join(attr)).ok();
+        let parse = |attr: &str| read(attr)?.trim().parse::<f32>().ok();
+
+        if read("type")?.trim() != "Battery" {
+            return None;
+        }
+
+        // the batteries of peripherals such as mice, not of the system
+        if read("scope").is_some_and(|s| s.trim() == "Device") {
+            return None;
+        }
+
+        // sysfs is in micro units
+        let micro = |attr: &str| parse(attr).map(|v| v / 1e6);
+        let voltage = micro("voltage_now");
+        let energy = |energy, charge| micro(energy).or_else(|| Some(micro(charge)? * voltage?));
+
+        Some(Self {
+            name: dir.file_name()?.to_string_lossy().into_owned(),
+            capacity: parse("capacity")?,
+            status: read("status").map_or(Status::Unknown, |s| Status::parse(s.trim())),
+            energy: energy("energy_now", "charge_now"),
+            energy_full: energy("energy_full", "charge_full"),
+            // some drivers report negative while discharging
+            power: micro("power_now")
+                .or_else(|| Some(micro("current_now")? * voltage?))
+                .map(f32::abs),
+        })
     }
 }
 
-use core::fmt::Display;
+/// reads batteries, smooths the power to estimate the remaining time, and raises alerts
+#[derive(Debug)]
+struct BatteryMonitor {
+    sysfs: PathBuf,
+    config: AlertConfig,
+    status: Status,
+    /// exponential moving average in watts
+    smoothed: Option<f32>,
+    alert: Alert,
+    /// spawned commands, to be reaped
+    hooks: Vec<std::process::Child>,
+}
 
-impl Display for MissingError {
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        write!(f, "Missing {}", self.0)
+impl BatteryMonitor {
+    /// weight of the latest power in the average
+    const SMOOTHING: f32 = 0.3;
+
+    fn new(config: &BatteryConfig) -> Self {
+        Self {
+            sysfs: config.sysfs.clone(),
+            config: config.alert.clone(),
+            status: Status::Unknown,
+            smoothed: None,
+            alert: Alert::Normal,
+            hooks: Vec::new(),
+        }
     }
-}
 
-impl Error for MissingError {}
+    /// updates the alert, and runs the command if it's raised
+    fn alert(&mut self, level: f32, status: Status) -> Alert {
+        self.hooks.retain_mut(|c| matches!(c.try_wait(), Ok(None)));
 
-// --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
+        let alert = match status {
+            Status::Discharging => self.alert.next(level, &self.config),
+            // the level can't go down while plugged in
+            _ => Alert::Normal,
+        };
 
-use wayland_client::protocol::wl_compositor::WlCompositor;
-use wayland_client::protocol::wl_output::WlOutput;
-use wayland_client::protocol::wl_shm::WlShm;
-use wayland_protocols_wlr::layer_shell::v1::client::zwlr_layer_shell_v1::ZwlrLayerShellV1;
+        if alert > self.alert {
+            if let Some(command) = alert.command(&self.config) {
+                let spawned = std::process::Command::new("sh")
+                    .arg("-c")
+                    .arg(command)
+                    .env("BANANAR_BATTERY_LEVEL", level.round().to_string())
+                    .spawn();
+
+                match spawned {
+                    Ok(child) => self.hooks.push(child),
+                    Err(e) => {
+                        eprintln!("failed to run the command of {} alert: {e}", alert.as_str())
+                    }
+                }
+            }
+        }
+
+        self.alert = alert;
+
+        alert
+    }
+
+    fn read(&mut self) -> BatteryState {
+        let batteries = read_batteries(&self.sysfs);
+        let ac_online = read_adapters(&self.sysfs).iter().any(|a| a.online);
+
+        if batteries.is_empty() {
+            self.status = Status::Unknown;
+            self.smoothed = None;
+            self.alert = Alert::Normal;
+
+            return BatteryState {
+                ac_online,
+                ..Default::default()
+            };
+        }
 
+        let energy = batteries.iter().map(|b| b.energy).sum::<Option<f32>>();
+        let energy_full = batteries.iter().map(|b| b.energy_full).sum::<Option<f32>>();
+        let power = batteries.iter().map(|b| b.power).sum::<Option<f32>>();
+
+        // weighted by the full capacities if all of them are known
+        let combined = match energy_full {
+            Some(full) if full > 0.0 => {
+                batteries
+                    .iter()
+                    .map(|b| b.capacity * b.energy_full.unwrap_or_default())
+                    .sum::<f32>()
+                    / full
+            }
+            _ => batteries.iter().map(|b| b.capacity).sum::<f32>() / batteries.len() as f32,
+        };
+
+        let status = batteries
+            .iter()
+            .map(|b| b.status)
+            .fold(Status::Unknown, Status::combine);
+
+        // the rate jumps when plugged or unplugged
+        if status != self.status {
+            self.status = status;
+            self.smoothed = None;
+        }
+
+        self.smoothed = match (self.smoothed, power) {
+            (Some(avg), Some(p)) => Some(avg + Self::SMOOTHING * (p - avg)),
+            (_, p) => p,
+        };
+
+        let hours = match (status, self.smoothed) {
+            (_, None) => None,
+            (_, Some(p)) if p <= 0.0 => None,
+            (Status::Discharging, Some(p)) => energy.map(|e| e / p),
+            (Status::Charging, Some(p)) => {
+                energy.zip(energy_full).map(|(e, f)| (f - e).max(0.0) / p)
+            }
+            _ => None,
+        };
+
+        let alert = self.alert(combined, status);
+
+        BatteryState {
+            combined: slint::format!("{}%", combined.round()),
+            each: batteries
+                .iter()
+                .map(|b| slint::format!("{} {}%", b.name, b.capacity))
+                .collect(),
+            status,
+            power: power.map_or_else(Default::default, |p| slint::format!("{p:.1}W")),
+            eta: hours.map_or_else(Default::default, |h| {
+                let minutes = (h * 60.0).round() as u64;
+
+                slint::format!("{}:{:02}", minutes / 60, minutes % 60)
+            }),
+            alert,
+            ac_online,
+        }
+    }
+}
+
+/// a `power_supply` device of `type == Mains` or any of USB ones
 #[derive(Debug)]
-struct InitialGateState {
-    compositor: Option<WlCompositor>,
-    shm: Option<WlShm>,
-    layer_shell: Option<ZwlrLayerShellV1>,
-    outputs: Vec<LazyBind<WlOutput>>,
-    pp: Option<PixelProxy>,
+struct Adapter {
+    online: bool,
 }
 
-impl Default for InitialGateState {
-    fn default() -> Self {
-        Self {
-            compositor: None,
-            shm: None,
-            layer_shell: None,
-            outputs: Vec::new(),
-            pp: None,
+impl Adapter {
+    fn read(dir: &Path) -> Option<Self> {
+        let read = |attr: &str| std::fs::read_to_string(dir.join(attr)).ok();
+
+        // `USB`, `USB_PD`, `USB_C` and so on
+        let kind = read("type")?;
+        if kind.trim() != "Mains" && !kind.starts_with("USB") {
+            return None;
         }
+
+        Some(Self {
+            online: read("online").is_some_and(|s| s.trim() == "1"),
+        })
     }
 }
 
-impl InitialGateState {
-    fn forward(self, handle: &QueueHandle<PrepareGateState>) -> Result<PrepareGateState> {
-        let compositor = self
-            .compositor
-            .ok_or_else(|| MissingError::new("wl_compositor"))?;
+/// enumerates `<sysfs>/class/power_supply/*`
+fn read_adapters(sysfs: &Path) -> Vec<Adapter> {
+    let Ok(entries) = std::fs::read_dir(sysfs.join("class/power_supply")) else {
+        return Vec::new();
+    };
 
-        #[rustfmt::skip]
-        let shm = self
-            .shm
-            .ok_or_else(|| MissingError::new("wl_shm"))?;
+    entries
+        .filter_map(|e| Adapter::read(&e.ok()?.path()))
+        .collect()
+}
 
-        let layer_shell = self
-            .layer_shell
-            .ok_or_else(|| MissingError::new("zwlr_layer_shell_v1"))?;
+/// enumerates `<sysfs>/class/power_supply/*`, sorted by the name
+fn read_batteries(sysfs: &Path) -> Vec<Battery> {
+    let Ok(entries) = std::fs::read_dir(sysfs.join("class/power_supply")) else {
+        return Vec::new();
+    };
 
-        let Some(pp) = self.pp else {
-            return Err(MissingError::new("unimplemented proxy of pixel").into());
-        };
+    let mut batteries = entries
+        .filter_map(|e| Battery::read(&e.ok()?.path()))
+        .collect::<Vec<_>>();
 
-        let outputs = self.outputs.iter().map(|lb| lb.bind(handle, ())).collect();
+    batteries.sort_by(|a, b| a.name.cmp(&b.name));
 
-        Ok(PrepareGateState {
-            compositor,
-            shm,
-            layer_shell,
-            outputs,
-            modes: HashMap::new(),
-            pp,
-        })
-    }
+    batteries
 }
 
 // --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
 
-use core::marker::PhantomData;
-
+/// kobject uevents broadcasted by the kernel over netlink
 #[derive(Debug)]
-struct LazyBind<I> {
-    registry: WlRegistry,
-    name: u32,
-    version: u32,
-    _phantom: PhantomData<I>,
+struct UeventListener {
+    socket: OwnedFd,
 }
 
-impl<I: Proxy + 'static> LazyBind<I> {
-    fn new(registry: WlRegistry, name: u32, version: u32) -> Self {
-        Self {
-            registry,
-            name,
-            version,
+impl UeventListener {
+    fn new() -> Result<Self> {
+        use nix::sys::socket::AddressFamily;
+        use nix::sys::socket::NetlinkAddr;
+        use nix::sys::socket::SockFlag;
+        use nix::sys::socket::SockProtocol;
+        use nix::sys::socket::SockType;
+
+        let flag = SockFlag::SOCK_NONBLOCK | SockFlag::SOCK_CLOEXEC;
+        let socket = nix::sys::socket::socket(
+            AddressFamily::Netlink,
+            SockType::Datagram,
+            flag,
+            SockProtocol::NetlinkKObjectUEvent,
+        )?;
+
+        // the group of the kernel, not of udevd
+        let addr = NetlinkAddr::new(0, 1);
+        nix::sys::socket::bind(socket.as_raw_fd(), &addr)?;
+
+        Ok(Self { socket })
+    }
 
-            _phantom: PhantomData,
+    /// to be called when readable, returns whether any event of `subsystem` is received
+    fn changed(&self, subsystem: &str) -> Result<bool> {
+        use nix::sys::socket::MsgFlags;
+
+        let key = format!("SUBSYSTEM={subsystem}");
+        let mut buf = [0; 8192];
+        let mut changed = false;
+
+        // consumes all of queued messages
+        loop {
+            let len = match nix::sys::socket::recv(
+                self.socket.as_raw_fd(),
+                &mut buf,
+                MsgFlags::empty(),
+            ) {
+                Ok(len) => len,
+                Err(nix::errno::Errno::EAGAIN) => return Ok(changed),
+
+                // the queue is overflowed on bursts such as docking, so the lost ones may be of it
+                Err(nix::errno::Errno::ENOBUFS) => {
+                    changed = true;
+                    continue;
+                }
+
+                Err(e) => return Err(e.into()),
+            };
+
+            // `ACTION@DEVPATH`, and then `KEY=VALUE`s, separated by NUL
+            changed |= buf[..len]
+                .split(|b| *b == 0)
+                .skip(1)
+                .any(|field| field == key.as_bytes());
         }
     }
+}
 
-    fn bind<U: Send + Sync + 'static, D: Dispatch<I, U> + 'static>(
-        &self,
-        handle: &QueueHandle<D>,
-        data: U,
-    ) -> I {
-        self.registry.bind(self.name, self.version, handle, data)
+impl AsFd for UeventListener {
+    fn as_fd(&self) -> std::os::fd::BorrowedFd<'_> {
+        self.socket.as_fd()
     }
 }
 
 // --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
 
-use slint::platform::software_renderer::PremultipliedRgbaColor as Pixel;
+use core::time::Duration;
+use nix::sys::timerfd::TimerFd;
+use std::os::fd::BorrowedFd;
+use std::sync::Arc;
 
-#[derive(Debug, Clone, Copy)]
-enum PixelProxy {
-    Rgba8888,
-    Argb8888,
+/// a source of values represented on the bar
+trait Module {
+    fn schedule(&self) -> Schedule<'_>;
+
+    /// gives the notifier if `Schedule::push` is set, called once before polling
+    fn connect(&mut self, _: Notifier) {}
+
+    /// takes over the state from the last output of the same kind before reloading
+    fn resume(&mut self, _: &Output) {}
+
+    /// reads the current state, returns `None` if unchanged since the last
+    fn poll(&mut self, trigger: Trigger) -> Result<Option<Output>>;
 }
 
-impl PixelProxy {
-    fn as_converter(&self) -> fn(Pixel) -> Pixel {
-        match self {
+/// which of `Schedule` gets ready, one per polling even if some of them get ready at once
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Trigger {
+    /// polled once on registering, before any of them
+    Start,
+    Interval,
+    /// any of `Schedule::fds`, the module tells which one
+    Fd,
+    Push,
+}
+
+/// what triggers `Module::poll`, any of them can be combined
+#[derive(Debug, Default)]
+struct Schedule<'a> {
+    /// polled periodically by a timer of the registry
+    interval: Option<Duration>,
+    /// polled when any of them gets readable
+    fds: Vec<BorrowedFd<'a>>,
+    /// polled when notified through `Notifier`
+    push: bool,
+}
+
+/// wakes the event loop up from other threads to poll the module
+#[derive(Debug, Clone)]
+struct Notifier(Arc<OwnedFd>);
+
+impl Notifier {
+    // none of built-in modules runs its own thread for now
+    #[allow(dead_code)]
+    fn notify(&self) {
+        // the counter saturates rather than blocks, and it's consumed anyway
+        let _ = nix::unistd::write(self.0.as_raw_fd(), &1u64.to_ne_bytes());
+    }
+}
+
+/// typed outputs of modules
+#[derive(Debug, Clone, PartialEq)]
+enum Output {
+    Battery(BatteryState),
+    Clock(ClockState),
+}
+
+impl Output {
+    fn kind(&self) -> ModuleKind {
+        match self {
+            Self::Battery(_) => ModuleKind::Battery,
+            Self::Clock(_) => ModuleKind::Clock,
+        }
+    }
+
+    /// represented in the sections
+    fn segment(&self) -> Segment {
+        match self {
+            // batteries may not be charging at the charge limits, though plugged in
+            Self::Battery(b) => Segment {
+                text: match b.eta.is_empty() {
+                    true => b.combined.clone(),
+                    false => slint::format!("{} {}", b.combined, b.eta),
+                },
+                // the alert wins, since weak supplies may not keep up with the drain
+                state: match (b.alert, b.ac_online || b.status == Status::Charging) {
+                    (Alert::Normal, true) => "charging".into(),
+                    (alert, _) => alert.as_str().into(),
+                },
+            },
+
+            Self::Clock(c) => Segment {
+                text: core::iter::once(&c.local)
+                    .chain(&c.zones)
+                    .map(|s| s.as_str())
+                    .collect::<Vec<_>>()
+                    .join("  ")
+                    .into(),
+                state: Default::default(),
+            },
+        }
+    }
+
+    /// binds the properties dedicated to the module
+    fn apply(&self, ui: &Ui) {
+        match self {
+            Self::Battery(b) => ui.set_battery(b),
+            Self::Clock(c) => ui.set_clock(c),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Left,
+    Center,
+    Right,
+}
+
+struct ModuleSlot {
+    section: Section,
+    module: Box<dyn Module>,
+    timer: Option<TimerFd>,
+    notifier: Option<Notifier>,
+    output: Option<Output>,
+}
+
+/// modules listed in the config, polled by `EventLoop` with `Token::Module`
+struct Registry {
+    slots: Vec<ModuleSlot>,
+}
+
+impl Registry {
+    fn new(config: &Config, event_loop: &EventLoop) -> Result<Self> {
+        Self::build(config, event_loop, Vec::new())
+    }
+
+    /// rebuilds all of modules, each of them resumed from the old one of the same kind
+    fn reload(self, config: &Config, event_loop: &EventLoop) -> Result<Self> {
+        // fds of the old one are closed by dropping, and so removed from epoll
+        let previous = self.slots.into_iter().filter_map(|s| s.output).collect();
+
+        Self::build(config, event_loop, previous)
+    }
+
+    fn build(config: &Config, event_loop: &EventLoop, mut previous: Vec<Output>) -> Result<Self> {
+        let ModulesConfig {
+            left,
+            center,
+            right,
+        } = &config.modules;
+
+        let sections = [
+            (Section::Left, left),
+            (Section::Center, center),
+            (Section::Right, right),
+        ];
+
+        let mut slots = Vec::new();
+
+        for (section, kinds) in sections {
+            for kind in kinds {
+                let index = slots.len();
+                let mut module = kind.build(config)?;
+
+                if let Some(i) = previous.iter().position(|o| o.kind() == *kind) {
+                    module.resume(&previous.remove(i));
+                }
+
+                let slot = Self::register(section, module, index, event_loop)?;
+
+                slots.push(slot);
+            }
+        }
+
+        Ok(Self { slots })
+    }
+
+    fn register(
+        section: Section,
+        mut module: Box<dyn Module>,
+        index: usize,
+        event_loop: &EventLoop,
+    ) -> Result<ModuleSlot> {
+        let token = Token::Module(index);
+
+        let (interval, push) = {
+            let schedule = module.schedule();
+
+            for fd in &schedule.fds {
+                event_loop.add(fd, token)?;
+            }
+
+            (schedule.interval, schedule.push)
+        };
+
+        let timer = match interval {
+            Some(interval) => {
+                use nix::sys::timerfd::ClockId;
+                use nix::sys::timerfd::Expiration;
+                use nix::sys::timerfd::TimerFlags;
+                use nix::sys::timerfd::TimerSetTimeFlags;
+
+                let flag = TimerFlags::TFD_NONBLOCK | TimerFlags::TFD_CLOEXEC;
+                let timer = TimerFd::new(ClockId::CLOCK_MONOTONIC, flag)?;
+
+                let expiration = Expiration::Interval(interval.into());
+                timer.set(expiration, TimerSetTimeFlags::empty())?;
+
+                event_loop.add(&timer, token)?;
+
+                Some(timer)
+            }
+            None => None,
+        };
+
+        let notifier = match push {
+            true => {
+                use nix::sys::eventfd::EfdFlags;
+
+                let flag = EfdFlags::EFD_NONBLOCK | EfdFlags::EFD_CLOEXEC;
+                let notifier = Notifier(Arc::new(nix::sys::eventfd::eventfd(0, flag)?));
+
+                event_loop.add(&*notifier.0, token)?;
+                module.connect(notifier.clone());
+
+                Some(notifier)
+            }
+            false => None,
+        };
+
+        // represents something before the first trigger
+        let output = module.poll(Trigger::Start)?;
+
+        Ok(ModuleSlot {
+            section,
+            module,
+            timer,
+            notifier,
+            output,
+        })
+    }
+
+    /// to be called when `Token::Module` gets ready, returns whether the output is changed
+    fn poll(&mut self, index: usize) -> Result<bool> {
+        // may be a stale token of the registry before reloading
+        let Some(slot) = self.slots.get_mut(index) else {
+            return Ok(false);
+        };
+
+        // consumes one of the expirations and the notifications,
+        // and the rest is left to the next since epoll is level-triggered
+        let expired = match &slot.timer {
+            Some(timer) => match timer.wait() {
+                Ok(()) => true,
+                Err(nix::errno::Errno::EAGAIN) => false,
+                Err(e) => return Err(e.into()),
+            },
+            None => false,
+        };
+
+        let notified = match &slot.notifier {
+            Some(_) if expired => false,
+            Some(notifier) => {
+                let mut buf = [0; 8];
+
+                match nix::unistd::read(notifier.0.as_raw_fd(), &mut buf) {
+                    Ok(_) => true,
+                    Err(nix::errno::Errno::EAGAIN) => false,
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            None => false,
+        };
+
+        let trigger = match (expired, notified) {
+            (true, _) => Trigger::Interval,
+            (_, true) => Trigger::Push,
+            _ => Trigger::Fd,
+        };
+
+        let Some(output) = slot.module.poll(trigger)? else {
+            return Ok(false);
+        };
+
+        slot.output = Some(output);
+
+        Ok(true)
+    }
+
+    fn apply(&self, ui: &Ui) {
+        for section in [Section::Left, Section::Center, Section::Right] {
+            let segments = self
+                .slots
+                .iter()
+                .filter(|s| s.section == section)
+                .filter_map(|s| s.output.as_ref())
+                .map(Output::segment)
+                .collect();
+
+            ui.set_section(section, segments);
+        }
+
+        for output in self.slots.iter().filter_map(|s| s.output.as_ref()) {
+            output.apply(ui);
+        }
+    }
+}
+
+impl core::fmt::Debug for Registry {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Registry").finish_non_exhaustive()
+    }
+}
+
+/// batteries, refreshed on uevents of `power_supply` and polled as the fallback
+struct BatteryModule {
+    monitor: BatteryMonitor,
+    interval: Duration,
+    uevent: Option<UeventListener>,
+    last: Option<BatteryState>,
+}
+
+impl BatteryModule {
+    fn new(config: &BatteryConfig) -> Self {
+        let uevent = match UeventListener::new() {
+            Ok(listener) => Some(listener),
+            Err(e) => {
+                eprintln!("batteries are only polled: {e}");
+                None
+            }
+        };
+
+        Self {
+            monitor: BatteryMonitor::new(config),
+            interval: config.interval,
+            uevent,
+            last: None,
+        }
+    }
+}
+
+impl Module for BatteryModule {
+    fn resume(&mut self, last: &Output) {
+        if let Output::Battery(last) = last {
+            self.monitor.alert = last.alert;
+        }
+    }
+
+    fn schedule(&self) -> Schedule<'_> {
+        Schedule {
+            interval: Some(self.interval),
+            fds: self.uevent.iter().map(|u| u.as_fd()).collect(),
+            push: false,
+        }
+    }
+
+    fn poll(&mut self, trigger: Trigger) -> Result<Option<Output>> {
+        let changed = match (trigger, &self.uevent) {
+            (Trigger::Fd, Some(uevent)) => uevent.changed("power_supply")?,
+            (Trigger::Fd, None) | (Trigger::Push, _) => false,
+            (Trigger::Start | Trigger::Interval, _) => true,
+        };
+
+        // only uevents of the other subsystems
+        if !changed {
+            return Ok(None);
+        }
+
+        let state = self.monitor.read();
+
+        if self.last.as_ref() == Some(&state) {
+            return Ok(None);
+        }
+
+        self.last = Some(state.clone());
+
+        Ok(Some(Output::Battery(state)))
+    }
+}
+
+/// the local time and extra time zones from the system tzdb
+#[derive(Debug, Clone, Default, PartialEq)]
+struct ClockState {
+    local: slint::SharedString,
+    /// labeled by the names of the zones
+    zones: Vec<slint::SharedString>,
+}
+
+/// woken up at the boundaries of seconds or minutes, rather than by a fixed interval
+struct ClockModule {
+    format: String,
+    local: tz::TimeZone,
+    zones: Vec<(String, tz::TimeZone)>,
+    /// whether `format` shows seconds, or only minutes
+    seconds: bool,
+    /// on `CLOCK_REALTIME`, so that it's not delayed by suspending
+    timer: TimerFd,
+    last: Option<ClockState>,
+}
+
+impl ClockModule {
+    fn new(config: &ClockConfig) -> Result<Self> {
+        use nix::sys::timerfd::ClockId;
+        use nix::sys::timerfd::TimerFlags;
+
+        let zones = config
+            .zones
+            .iter()
+            .filter_map(|name| match tz::TimeZone::from_posix_tz(name) {
+                Ok(zone) => Some((name.clone(), zone)),
+                Err(e) => {
+                    eprintln!("failed to load the time zone {name}: {e}");
+                    None
+                }
+            })
+            .collect();
+
+        let flag = TimerFlags::TFD_NONBLOCK | TimerFlags::TFD_CLOEXEC;
+        let timer = TimerFd::new(ClockId::CLOCK_REALTIME, flag)?;
+
+        let module = Self {
+            format: config.format.clone(),
+            local: Self::local(),
+            zones,
+            seconds: shows_seconds(&config.format),
+            timer,
+            last: None,
+        };
+
+        module.arm()?;
+
+        Ok(module)
+    }
+
+    /// `TZ` or `/etc/localtime`, or UTC if neither is available
+    fn local() -> tz::TimeZone {
+        // `TimeZone::utc` has no designation for `%Z`
+        tz::TimeZone::local()
+            .or_else(|_| tz::TimeZone::from_posix_tz("UTC0"))
+            .unwrap_or_else(|_| tz::TimeZone::utc())
+    }
+
+    /// sets the timer to the next boundary, canceled if the clock is set discontinuously
+    fn arm(&self) -> Result {
+        use nix::sys::time::TimeSpec;
+        use nix::sys::timerfd::Expiration;
+        use nix::sys::timerfd::TimerSetTimeFlags;
+
+        let step = if self.seconds { 1 } else { 60 };
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        let next = (now / step + 1) * step;
+
+        let expiration = Expiration::OneShot(TimeSpec::new(next.try_into()?, 0));
+        let flag = TimerSetTimeFlags::from_bits_retain(
+            libc::TFD_TIMER_ABSTIME | libc::TFD_TIMER_CANCEL_ON_SET,
+        );
+        self.timer.set(expiration, flag)?;
+
+        Ok(())
+    }
+
+    /// `now` is since the unix epoch
+    fn format_in(
+        &self,
+        zone: &tz::TimeZone,
+        now: std::time::Duration,
+    ) -> Option<slint::SharedString> {
+        let secs = now.as_secs().try_into().ok()?;
+        let local = zone.find_local_time_type(secs).ok()?;
+
+        let offset = chrono::FixedOffset::east_opt(local.ut_offset())?;
+        let time =
+            chrono::DateTime::from_timestamp(secs, now.subsec_nanos())?.with_timezone(&offset);
+
+        use chrono::format::Fixed;
+        use chrono::format::Item;
+        use chrono::format::StrftimeItems;
+
+        // `FixedOffset` doesn't know the abbreviation such as JST
+        let designation = local.time_zone_designation();
+        let items = StrftimeItems::new(&self.format).map(|i| match i {
+            Item::Fixed(Fixed::TimezoneName) => Item::Literal(designation),
+            i => i,
+        });
+
+        Some(slint::format!("{}", time.format_with_items(items)))
+    }
+}
+
+impl Module for ClockModule {
+    fn schedule(&self) -> Schedule<'_> {
+        Schedule {
+            interval: None,
+            fds: vec![self.timer.as_fd()],
+            push: false,
+        }
+    }
+
+    fn poll(&mut self, _: Trigger) -> Result<Option<Output>> {
+        match self.timer.wait() {
+            Ok(()) | Err(nix::errno::Errno::EAGAIN) => (),
+
+            // the clock is set, or the time zone may be changed
+            Err(nix::errno::Errno::ECANCELED) => self.local = Self::local(),
+
+            Err(e) => return Err(e.into()),
+        }
+
+        self.arm()?;
+
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?;
+
+        let state = ClockState {
+            local: self.format_in(&self.local, now).unwrap_or_default(),
+            zones: self
+                .zones
+                .iter()
+                .map(|(name, zone)| {
+                    let time = self.format_in(zone, now).unwrap_or_default();
+
+                    slint::format!("{name} {time}")
+                })
+                .collect(),
+        };
+
+        if self.last.as_ref() == Some(&state) {
+            return Ok(None);
+        }
+
+        self.last = Some(state.clone());
+
+        Ok(Some(Output::Clock(state)))
+    }
+}
+
+/// whether the strftime-style format has any of seconds, or finer
+fn shows_seconds(format: &str) -> bool {
+    use chrono::format::Fixed;
+    use chrono::format::Item;
+    use chrono::format::Numeric;
+    use chrono::format::StrftimeItems;
+
+    StrftimeItems::new(format).any(|item| {
+        matches!(
+            item,
+            Item::Numeric(
+                Numeric::Second | Numeric::Timestamp | Numeric::Nanosecond,
+                _
+            ) | Item::Fixed(
+                Fixed::Nanosecond
+                    | Fixed::Nanosecond3
+                    | Fixed::Nanosecond6
+                    | Fixed::Nanosecond9
+                    | Fixed::RFC2822
+                    | Fixed::RFC3339
+            )
+        )
+    })
+}
+
+impl core::fmt::Debug for ClockModule {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ClockModule")
+            .field("format", &self.format)
+            .finish_non_exhaustive()
+    }
+}
+
+// --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
+
+use nix::sys::epoll::Epoll;
+use nix::sys::epoll::EpollEvent;
+use nix::sys::epoll::EpollFlags;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Wayland,
+    Config,
+    Module(usize),
+}
+
+impl Token {
+    fn into_raw(self) -> u64 {
+        match self {
+            Self::Wayland => 0,
+            Self::Config => 1,
+            Self::Module(index) => 2 + index as u64,
+        }
+    }
+
+    fn from_raw(raw: u64) -> Option<Self> {
+        match raw {
+            0 => Some(Self::Wayland),
+            1 => Some(Self::Config),
+            raw => Some(Self::Module((raw - 2).try_into().ok()?)),
+        }
+    }
+}
+
+/// multiplexes the connection of wayland, timers and data sources by epoll
+#[derive(Debug)]
+struct EventLoop {
+    epoll: Epoll,
+}
+
+impl EventLoop {
+    fn new() -> Result<Self> {
+        use nix::sys::epoll::EpollCreateFlags;
+        let epoll = Epoll::new(EpollCreateFlags::EPOLL_CLOEXEC)?;
+
+        Ok(Self { epoll })
+    }
+
+    fn add(&self, fd: impl AsFd, token: Token) -> Result {
+        let event = EpollEvent::new(EpollFlags::EPOLLIN, token.into_raw());
+        self.epoll.add(fd, event)?;
+
+        Ok(())
+    }
+
+    fn delete(&self, fd: impl AsFd) -> Result {
+        self.epoll.delete(fd)?;
+
+        Ok(())
+    }
+
+    /// blocks until some sources get ready or `timeout` is elapsed, returns the ready ones
+    ///
+    /// events read from the connection are only queued, so dispatch them with `dispatch_pending`
+    fn wait(&self, connection: &Connection, timeout: Option<Duration>) -> Result<Vec<Token>> {
+        connection.flush()?;
+
+        // some events are already read and queued
+        let Some(guard) = connection.prepare_read() else {
+            return Ok(vec![Token::Wayland]);
+        };
+
+        let timeout = match timeout {
+            // rounds up, or it may spin until the deadline
+            Some(d) => d
+                .as_micros()
+                .div_ceil(1000)
+                .try_into()
+                .unwrap_or(isize::MAX),
+            None => -1,
+        };
+
+        let mut events = [EpollEvent::empty(); 16];
+        let len = match self.epoll.wait(&mut events, timeout) {
+            Err(nix::errno::Errno::EINTR) => 0,
+            r => r?,
+        };
+
+        let ready = events[..len]
+            .iter()
+            .filter_map(|e| Token::from_raw(e.data()))
+            .collect::<Vec<_>>();
+
+        if ready.contains(&Token::Wayland) {
+            match guard.read() {
+                Err(WaylandError::Io(e)) if e.kind() == std::io::ErrorKind::WouldBlock => (),
+                r => _ = r?,
+            }
+        }
+
+        Ok(ready)
+    }
+}
+
+// --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
+
+use core::cell::Cell;
+use core::cell::RefCell;
+use slint::ComponentHandle;
+use slint_interpreter::ComponentDefinition;
+use slint_interpreter::ComponentInstance;
+use std::rc::Rc;
+use std::time::Instant;
+
+use slint::platform::software_renderer::MinimalSoftwareWindow;
+use slint::platform::WindowAdapter;
+
+fn init_platform() -> Result<WindowFactory> {
+    let created = Rc::new(Cell::new(None));
+    let definition = RefCell::new(None);
+    let platform = Platform {
+        start: Instant::now(),
+        created: created.clone(),
+    };
+
+    slint::platform::set_platform(Box::new(platform)).unwrap();
+
+    Ok(WindowFactory {
+        created,
+        definition,
+    })
+}
+
+struct Platform {
+    start: Instant,
+    created: Rc<Cell<Option<Rc<MinimalSoftwareWindow>>>>,
+}
+
+impl slint::platform::Platform for Platform {
+    fn create_window_adapter(&self) -> Result<Rc<dyn WindowAdapter>, slint::PlatformError> {
+        let window = MinimalSoftwareWindow::new(Default::default());
+
+        // correctness: adapters are taken by `WindowFactory::create` right after instantiation
+        let None = self.created.replace(Some(window.clone())) else {
+            unreachable!()
+        };
+
+        Ok(window)
+    }
+
+    fn duration_since_start(&self) -> core::time::Duration {
+        self.start.elapsed()
+    }
+}
+
+struct WindowFactory {
+    created: Rc<Cell<Option<Rc<MinimalSoftwareWindow>>>>,
+    /// the user component, the built-in `Main` is used if `None`
+    definition: RefCell<Option<ComponentDefinition>>,
+}
+
+impl WindowFactory {
+    /// compiles the user component, falls back to the built-in one if it fails
+    fn load(&self, path: Option<&Path>) {
+        let definition = path.and_then(|path| {
+            let mut compiler = slint_interpreter::ComponentCompiler::default();
+            let definition = spin_on::spin_on(compiler.build_from_path(path));
+
+            for d in compiler.diagnostics() {
+                let (line, column) = d.line_column();
+                eprintln!(
+                    "{}:{line}:{column}: {:?}: {}",
+                    path.display(),
+                    d.level(),
+                    d.message()
+                );
+            }
+
+            if definition.is_none() {
+                eprintln!("failed to compile {}, uses the built-in ui", path.display());
+            }
+
+            definition
+        });
+
+        self.definition.replace(definition);
+    }
+
+    fn create(&self) -> Result<View> {
+        let ui = match &*self.definition.borrow() {
+            Some(definition) => Ui::Interpreted(definition.create()?),
+            None => Ui::Builtin(Main::new()?),
+        };
+
+        let window = self
+            .created
+            .take()
+            .ok_or_else(|| MissingError::new("window adapter of slint"))?;
+
+        Ok(View { window, ui })
+    }
+}
+
+struct View {
+    window: Rc<MinimalSoftwareWindow>,
+    ui: Ui,
+}
+
+enum Ui {
+    Builtin(Main),
+    Interpreted(ComponentInstance),
+}
+
+impl Ui {
+    /// binds by the name, which the user component may not declare
+    fn set(&self, name: &str, value: slint_interpreter::Value, builtin: impl FnOnce(&Main)) {
+        match self {
+            Self::Builtin(ui) => builtin(ui),
+            Self::Interpreted(ui) => {
+                let _ = ui.set_property(name, value);
+            }
+        }
+    }
+
+    fn set_battery(&self, battery: &BatteryState) {
+        use slint::ModelRc;
+        use slint::VecModel;
+        use slint_interpreter::Value;
+
+        let combined = battery.combined.clone();
+        self.set("battery-level", Value::String(combined.clone()), |ui| {
+            ui.set_battery_level(combined)
+        });
+
+        let each = ModelRc::new(VecModel::from(battery.each.clone()));
+        let values = ModelRc::new(VecModel::from(
+            battery
+                .each
+                .iter()
+                .cloned()
+                .map(Value::String)
+                .collect::<Vec<_>>(),
+        ));
+        self.set("batteries", Value::Model(values), |ui| {
+            ui.set_batteries(each)
+        });
+
+        let status = slint::SharedString::from(battery.status.as_str());
+        self.set("battery-status", Value::String(status.clone()), |ui| {
+            ui.set_battery_status(status)
+        });
+
+        let power = battery.power.clone();
+        self.set("battery-power", Value::String(power.clone()), |ui| {
+            ui.set_battery_power(power)
+        });
+
+        let eta = battery.eta.clone();
+        self.set("battery-eta", Value::String(eta.clone()), |ui| {
+            ui.set_battery_eta(eta)
+        });
+
+        let alert = slint::SharedString::from(battery.alert.as_str());
+        self.set("battery-alert", Value::String(alert.clone()), |ui| {
+            ui.set_battery_alert(alert)
+        });
+
+        let ac_online = battery.ac_online;
+        self.set("ac-online", Value::Bool(ac_online), |ui| {
+            ui.set_ac_online(ac_online)
+        });
+    }
+
+    fn set_clock(&self, clock: &ClockState) {
+        use slint::ModelRc;
+        use slint::VecModel;
+        use slint_interpreter::Value;
+
+        let local = clock.local.clone();
+        self.set("clock", Value::String(local.clone()), |ui| {
+            ui.set_clock(local)
+        });
+
+        let zones = ModelRc::new(VecModel::from(clock.zones.clone()));
+        let values = ModelRc::new(VecModel::from(
+            clock
+                .zones
+                .iter()
+                .cloned()
+                .map(Value::String)
+                .collect::<Vec<_>>(),
+        ));
+        self.set("clocks", Value::Model(values), |ui| ui.set_clocks(zones));
+    }
+
+    fn set_section(&self, section: Section, segments: Vec<Segment>) {
+        use slint::ModelRc;
+        use slint::VecModel;
+        use slint_interpreter::Value;
+
+        let values = segments
+            .iter()
+            .map(|s| {
+                let fields = [
+                    ("text".to_owned(), Value::String(s.text.clone())),
+                    ("state".to_owned(), Value::String(s.state.clone())),
+                ];
+
+                Value::Struct(fields.into_iter().collect())
+            })
+            .collect::<Vec<_>>();
+        let values = Value::Model(ModelRc::new(VecModel::from(values)));
+        let segments = ModelRc::new(VecModel::from(segments));
+
+        match section {
+            Section::Left => self.set("left", values, |ui| ui.set_left(segments)),
+            Section::Center => self.set("middle", values, |ui| ui.set_middle(segments)),
+            Section::Right => self.set("right", values, |ui| ui.set_right(segments)),
+        }
+    }
+
+    fn show(&self) -> Result<(), slint::PlatformError> {
+        match self {
+            Self::Builtin(ui) => ui.show(),
+            Self::Interpreted(ui) => ui.show(),
+        }
+    }
+
+    fn hide(&self) -> Result<(), slint::PlatformError> {
+        match self {
+            Self::Builtin(ui) => ui.hide(),
+            Self::Interpreted(ui) => ui.hide(),
+        }
+    }
+}
+
+impl View {
+    /// `size` is in physical pixels, and the layout is done in `size / scale_factor`
+    fn resize(&self, size: Size, scale_factor: f32) -> Result {
+        use slint::platform::WindowEvent;
+
+        let width = size.width.try_into()?;
+        let height = size.height.try_into()?;
+
+        self.window
+            .dispatch_event(WindowEvent::ScaleFactorChanged { scale_factor });
+        self.window
+            .set_size(slint::PhysicalSize::new(width, height));
+
+        // `MinimalSoftwareWindow::set_size` lays out as the scale factor is 1, so corrects it
+        let size = slint::PhysicalSize::new(width, height).to_logical(scale_factor);
+        self.window.dispatch_event(WindowEvent::Resized { size });
+
+        Ok(())
+    }
+}
+
+impl core::fmt::Debug for View {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("View").finish_non_exhaustive()
+    }
+}
+
+// --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
+
+#[derive(Debug)]
+struct MissingError(String);
+
+impl MissingError {
+    fn new(content: impl Into<String>) -> Self {
+        Self(content.into())
+    }
+}
+
+use core::fmt::Display;
+
+impl Display for MissingError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Missing {}", self.0)
+    }
+}
+
+impl Error for MissingError {}
+
+// --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
+
+use wayland_client::protocol::wl_compositor::WlCompositor;
+use wayland_client::protocol::wl_output::WlOutput;
+use wayland_client::protocol::wl_shm::WlShm;
+use wayland_protocols::wp::fractional_scale::v1::client::wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1;
+use wayland_protocols::wp::viewporter::client::wp_viewporter::WpViewporter;
+use wayland_protocols::xdg::xdg_output::zv1::client::zxdg_output_manager_v1::ZxdgOutputManagerV1;
+use wayland_protocols_wlr::layer_shell::v1::client::zwlr_layer_shell_v1::ZwlrLayerShellV1;
+
+#[derive(Debug)]
+struct InitialGateState {
+    compositor: Option<WlCompositor>,
+    shm: Option<WlShm>,
+    layer_shell: Option<ZwlrLayerShellV1>,
+    fractional_scale_manager: Option<WpFractionalScaleManagerV1>,
+    viewporter: Option<WpViewporter>,
+    xdg_output_manager: Option<ZxdgOutputManagerV1>,
+    outputs: Vec<LazyBind<WlOutput>>,
+    pp: Option<PixelProxy>,
+}
+
+impl Default for InitialGateState {
+    fn default() -> Self {
+        Self {
+            compositor: None,
+            shm: None,
+            layer_shell: None,
+            fractional_scale_manager: None,
+            viewporter: None,
+            xdg_output_manager: None,
+            outputs: Vec::new(),
+            pp: None,
+        }
+    }
+}
+
+impl InitialGateState {
+    fn forward(self, handle: &QueueHandle<PrepareGateState>) -> Result<PrepareGateState> {
+        let compositor = self
+            .compositor
+            .ok_or_else(|| MissingError::new("wl_compositor"))?;
+
+        #[rustfmt::skip]
+        let shm = self
+            .shm
+            .ok_or_else(|| MissingError::new("wl_shm"))?;
+
+        let layer_shell = self
+            .layer_shell
+            .ok_or_else(|| MissingError::new("zwlr_layer_shell_v1"))?;
+
+        let Some(pp) = self.pp else {
+            return Err(MissingError::new("unimplemented proxy of pixel").into());
+        };
+
+        let outputs = self
+            .outputs
+            .iter()
+            .map(|lb| (lb.name, lb.bind(handle, ())))
+            .collect::<Vec<_>>();
+
+        // optional, falls back to the mode of `wl_output`
+        let xdg_outputs = match &self.xdg_output_manager {
+            Some(manager) => outputs
+                .iter()
+                .map(|(_, o)| manager.get_xdg_output(o, handle, o.id()))
+                .collect(),
+            None => Vec::new(),
+        };
+
+        // optional, falls back to integer scaling
+        let fractional = self.fractional_scale_manager.zip(self.viewporter);
+
+        let globals = Globals {
+            compositor,
+            shm,
+            layer_shell,
+            fractional,
+            xdg_output_manager: self.xdg_output_manager,
+            pp,
+        };
+
+        Ok(PrepareGateState {
+            globals,
+            outputs,
+            xdg_outputs,
+            infos: HashMap::new(),
+        })
+    }
+}
+
+// --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
+
+use core::marker::PhantomData;
+
+#[derive(Debug)]
+struct LazyBind<I> {
+    registry: WlRegistry,
+    name: u32,
+    version: u32,
+    _phantom: PhantomData<I>,
+}
+
+impl<I: Proxy + 'static> LazyBind<I> {
+    fn new(registry: WlRegistry, name: u32, version: u32) -> Self {
+        Self {
+            registry,
+            name,
+            version,
+
+            _phantom: PhantomData,
+        }
+    }
+
+    fn bind<U: Send + Sync + 'static, D: Dispatch<I, U> + 'static>(
+        &self,
+        handle: &QueueHandle<D>,
+        data: U,
+    ) -> I {
+        self.registry.bind(self.name, self.version, handle, data)
+    }
+}
+
+// --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
+
+use slint::platform::software_renderer::PremultipliedRgbaColor as Pixel;
+
+#[derive(Debug, Clone, Copy)]
+enum PixelProxy {
+    Rgba8888,
+    Argb8888,
+}
+
+impl PixelProxy {
+    fn as_converter(&self) -> fn(Pixel) -> Pixel {
+        match self {
             Self::Rgba8888 => Self::from_rgba8888,
             Self::Argb8888 => Self::from_argb8888,
         }
-    }
+    }
+
+    fn from_rgba8888(value: Pixel) -> Pixel {
+        value
+    }
+
+    fn from_argb8888(value: Pixel) -> Pixel {
+        let Pixel {
+            red: alpha,
+            green: red,
+            blue: green,
+            alpha: blue,
+        } = value;
+
+        Pixel {
+            red,
+            green,
+            blue,
+            alpha,
+        }
+    }
+}
+
+// --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
+
+use wayland_client::protocol::wl_registry::WlRegistry;
+use wayland_client::Dispatch;
+use wayland_client::Proxy;
+use wayland_client::QueueHandle;
+
+impl Dispatch<WlRegistry, ()> for InitialGateState {
+    fn event(
+        state: &mut Self,
+        registry: &WlRegistry,
+        event: <WlRegistry as Proxy>::Event,
+        (): &(),
+        _: &Connection,
+        handle: &QueueHandle<Self>,
+    ) {
+        type Event = <WlRegistry as Proxy>::Event;
+
+        let Event::Global {
+            name,
+            version,
+            interface,
+        } = event
+        else {
+            unreachable!()
+        };
+
+        if <WlCompositor as Proxy>::interface().name == interface {
+            let None = state
+                .compositor
+                .replace(registry.bind(name, version, handle, ()))
+            else {
+                unreachable!()
+            };
+        }
+
+        if <WlShm as Proxy>::interface().name == interface {
+            let None = state.shm.replace(registry.bind(name, version, handle, ())) else {
+                unreachable!()
+            };
+        }
+
+        if <ZwlrLayerShellV1 as Proxy>::interface().name == interface {
+            let None = state
+                .layer_shell
+                .replace(registry.bind(name, version, handle, ()))
+            else {
+                unreachable!()
+            };
+        }
+
+        if <WpFractionalScaleManagerV1 as Proxy>::interface().name == interface {
+            let None =
+                state
+                    .fractional_scale_manager
+                    .replace(registry.bind(name, version, handle, ()))
+            else {
+                unreachable!()
+            };
+        }
+
+        if <WpViewporter as Proxy>::interface().name == interface {
+            let None = state
+                .viewporter
+                .replace(registry.bind(name, version, handle, ()))
+            else {
+                unreachable!()
+            };
+        }
+
+        if <ZxdgOutputManagerV1 as Proxy>::interface().name == interface {
+            let None = state
+                .xdg_output_manager
+                .replace(registry.bind(name, version, handle, ()))
+            else {
+                unreachable!()
+            };
+        }
+
+        if <WlOutput as Proxy>::interface().name == interface {
+            state
+                .outputs
+                .push(LazyBind::new(registry.clone(), name, version));
+        }
+    }
+}
+
+// `WlCompositor` has no events
+wayland_client::delegate_noop!(InitialGateState: WlCompositor);
+
+impl Dispatch<WlShm, ()> for InitialGateState {
+    fn event(
+        state: &mut Self,
+        _: &WlShm,
+        event: <WlShm as Proxy>::Event,
+        (): &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        type Event = <WlShm as Proxy>::Event;
+
+        let Event::Format { format } = event else {
+            unreachable!()
+        };
+
+        use wayland_client::protocol::wl_shm::Format;
+        match format.into_result() {
+            Ok(Format::Rgba8888) => state.pp = Some(PixelProxy::Rgba8888),
+            Ok(Format::Argb8888) => state.pp = Some(PixelProxy::Argb8888),
+
+            _ => (),
+        }
+    }
+}
+
+// `ZwlrLayerShellV1` has no events
+wayland_client::delegate_noop!(InitialGateState: ZwlrLayerShellV1);
+
+// `WpFractionalScaleManagerV1` has no events
+wayland_client::delegate_noop!(InitialGateState: WpFractionalScaleManagerV1);
+
+// `WpViewporter` has no events
+wayland_client::delegate_noop!(InitialGateState: WpViewporter);
+
+// `ZxdgOutputManagerV1` has no events
+wayland_client::delegate_noop!(InitialGateState: ZxdgOutputManagerV1);
+
+// unallow to receive events
+wayland_client::delegate_noop!(InitialGateState: WlOutput);
+
+// --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
+
+use std::collections::HashMap;
+use wayland_client::backend::ObjectId;
+use wayland_protocols::xdg::xdg_output::zv1::client::zxdg_output_v1::ZxdgOutputV1;
+
+#[derive(Debug)]
+struct PrepareGateState {
+    globals: Globals,
+    outputs: Vec<(u32, WlOutput)>,
+    xdg_outputs: Vec<ZxdgOutputV1>,
+    infos: HashMap<ObjectId, OutputInfo>,
+}
+
+impl PrepareGateState {
+    fn forward(
+        mut self,
+        connection: &Connection,
+        factory: &WindowFactory,
+        config: &Config,
+        handle: &QueueHandle<ReadyGateState>,
+    ) -> Result<ReadyGateState> {
+        // the infos are already collected
+        for xdg_output in self.xdg_outputs.drain(..) {
+            xdg_output.destroy();
+        }
+
+        let windows = self
+            .outputs
+            .into_iter()
+            .map(|(name, output)| {
+                let info = self.infos.remove(&output.id()).unwrap_or_default();
+
+                Window::new(
+                    &self.globals,
+                    config,
+                    name,
+                    output,
+                    info,
+                    connection,
+                    factory,
+                )
+            })
+            .try_collect()?;
+
+        // listens `wl_output` globals to be added or removed from here
+        let registry = connection.display().get_registry(handle, ());
+
+        Ok(ReadyGateState {
+            globals: self.globals,
+            config: config.clone(),
+            registry,
+            windows,
+            pending: Vec::new(),
+        })
+    }
+}
+
+// --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
+
+#[derive(Debug, Clone)]
+struct Globals {
+    compositor: WlCompositor,
+    shm: WlShm,
+    layer_shell: ZwlrLayerShellV1,
+    fractional: Option<(WpFractionalScaleManagerV1, WpViewporter)>,
+    xdg_output_manager: Option<ZxdgOutputManagerV1>,
+    pp: PixelProxy,
+}
+
+// --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
+
+#[derive(Debug)]
+struct Mode {
+    width: usize,
+    height: usize,
+}
+
+/// properties of `wl_output` and `zxdg_output_v1` accumulated until its `done`
+#[derive(Debug)]
+struct OutputInfo {
+    /// the current one, in hardware pixels
+    mode: Option<Mode>,
+    scale: i32,
+    transform: Transform,
+    /// already transformed and scaled, only with `zxdg_output_v1`
+    logical: Option<Size>,
+    name: Option<String>,
+    description: Option<String>,
+}
+
+impl Default for OutputInfo {
+    fn default() -> Self {
+        Self {
+            mode: None,
+            scale: 1,
+            transform: Transform::Normal,
+            logical: None,
+            name: None,
+            description: None,
+        }
+    }
+}
+
+impl OutputInfo {
+    /// returns whether the event is `done`
+    fn update(&mut self, event: <WlOutput as Proxy>::Event) -> bool {
+        type Event = <WlOutput as Proxy>::Event;
+
+        match event {
+            Event::Mode {
+                flags,
+                width,
+                height,
+                ..
+            } => {
+                use wayland_client::protocol::wl_output::Mode as Flags;
+
+                // the others are just supported
+                if !flags
+                    .into_result()
+                    .is_ok_and(|f| f.contains(Flags::Current))
+                {
+                    return false;
+                }
+
+                self.mode = Some(Mode {
+                    width: width as usize,
+                    height: height as usize,
+                });
+            }
+
+            Event::Scale { factor } => self.scale = factor,
+
+            Event::Done => return true,
+
+            Event::Geometry { transform, .. } => {
+                self.transform = transform.into_result().unwrap_or(Transform::Normal)
+            }
+
+            // since v4, the same as of `zxdg_output_v1`
+            Event::Name { name } => self.name = Some(name),
+            Event::Description { description } => self.description = Some(description),
+
+            _ => unreachable!(),
+        }
+
+        false
+    }
+
+    /// returns whether the event is `done`, which is deprecated since v3 in favor of `wl_output`'s
+    fn update_xdg(&mut self, event: <ZxdgOutputV1 as Proxy>::Event) -> bool {
+        type Event = <ZxdgOutputV1 as Proxy>::Event;
+
+        match event {
+            Event::LogicalSize { width, height } => {
+                self.logical = Some(Size {
+                    width: width.max(0) as usize,
+                    height: height.max(0) as usize,
+                })
+            }
+
+            Event::Name { name } => self.name = Some(name),
+            Event::Description { description } => self.description = Some(description),
+
+            Event::Done => return true,
+
+            Event::LogicalPosition { .. } => (),
+
+            _ => unreachable!(),
+        }
+
+        false
+    }
+
+    /// in logical pixels, computed from the mode if `zxdg_output_v1` is unavailable
+    fn logical_size(&self) -> Option<Size> {
+        if let Some(logical) = self.logical {
+            return Some(logical);
+        }
+
+        // the mode is in the hardware orientation, portrait monitors report it transposed
+        let mode = self.mode.as_ref()?;
+        let mode = Size {
+            width: mode.width,
+            height: mode.height,
+        }
+        .transform(self.transform);
+
+        let scale = self.scale.max(1) as usize;
+
+        Some(Size {
+            width: mode.width / scale,
+            height: mode.height / scale,
+        })
+    }
+
+    fn label(&self) -> &str {
+        self.description
+            .as_deref()
+            .or(self.name.as_deref())
+            .unwrap_or("unknown output")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Size {
+    width: usize,
+    height: usize,
+}
+
+impl Size {
+    /// swaps width and height if `transform` rotates by 90 or 270 degrees
+    fn transform(self, transform: Transform) -> Self {
+        match is_transposed(transform) {
+            true => Self {
+                width: self.height,
+                height: self.width,
+            },
+            false => self,
+        }
+    }
+}
+
+// --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
+
+// unallow to receive events
+wayland_client::delegate_noop!(PrepareGateState: WlRegistry);
+wayland_client::delegate_noop!(PrepareGateState: WlCompositor);
+wayland_client::delegate_noop!(PrepareGateState: WlShm);
+wayland_client::delegate_noop!(PrepareGateState: ZwlrLayerShellV1);
+
+impl Dispatch<WlOutput, ()> for PrepareGateState {
+    fn event(
+        state: &mut Self,
+        output: &WlOutput,
+        event: <WlOutput as Proxy>::Event,
+        (): &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        state.infos.entry(output.id()).or_default().update(event);
+    }
+}
+
+impl Dispatch<ZxdgOutputV1, ObjectId> for PrepareGateState {
+    fn event(
+        state: &mut Self,
+        _: &ZxdgOutputV1,
+        event: <ZxdgOutputV1 as Proxy>::Event,
+        output: &ObjectId,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        state
+            .infos
+            .entry(output.clone())
+            .or_default()
+            .update_xdg(event);
+    }
+}
+
+// --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
+
+use core::ptr::NonNull;
+use std::os::fd::OwnedFd;
+
+#[derive(Debug)]
+struct Shm {
+    ptr: NonNull<[u8]>,
+    fd: OwnedFd,
+}
+
+impl Shm {
+    pub fn new(size: usize) -> Result<Self> {
+        let fd = Self::open()?;
+
+        let ptr = Self::mmap(&fd, size)?;
+        let ptr = core::ptr::slice_from_raw_parts_mut(ptr, size);
+        let ptr = NonNull::new(ptr).ok_or(Unhandled)?;
+
+        Ok(Self { ptr, fd })
+    }
+
+    fn open() -> Result<OwnedFd> {
+        let name = {
+            let ts = std::time::SystemTime::now().elapsed().unwrap().as_nanos();
+
+            format!("/wl-shm-{ts:32x}")
+        };
+
+        let fd = {
+            use nix::fcntl::OFlag;
+            use nix::sys::stat::Mode;
+
+            let flag = OFlag::O_RDWR | OFlag::O_CREAT | OFlag::O_EXCL;
+            let mode = Mode::S_IRUSR | Mode::S_IWUSR | Mode::S_IXUSR;
+
+            nix::sys::mman::shm_open(&*name, flag, mode)?
+        };
+
+        nix::sys::mman::shm_unlink(&*name)?;
+
+        Ok(fd)
+    }
+
+    fn mmap<F: AsFd>(fd: F, size: usize) -> Result<*mut u8> {
+        nix::unistd::ftruncate(&fd, size.try_into()?)?;
+
+        let ptr = {
+            use nix::sys::mman::MapFlags;
+            use nix::sys::mman::ProtFlags;
+
+            let pflag = ProtFlags::PROT_READ | ProtFlags::PROT_WRITE;
+            let mflag = MapFlags::MAP_SHARED;
+
+            unsafe { nix::sys::mman::mmap(None, size.try_into()?, pflag, mflag, Some(&fd), 0) }?
+        };
+
+        Ok(ptr.cast::<u8>())
+    }
+
+    pub fn resize(&mut self, size: usize) -> Result<()> {
+        nix::unistd::ftruncate(&self.fd, size.try_into()?)?;
+
+        let ptr = {
+            use nix::sys::mman::MRemapFlags;
+
+            let addr = self.ptr.as_mut_ptr().cast();
+            let flag = MRemapFlags::MREMAP_MAYMOVE;
+
+            unsafe { nix::sys::mman::mremap(addr, self.ptr.len(), size, flag, None) }?
+        };
+
+        let ptr = core::ptr::slice_from_raw_parts_mut(ptr.cast::<u8>(), size);
+        let ptr = NonNull::new(ptr).ok_or(Unhandled)?;
+
+        self.ptr = ptr;
+
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.ptr.len()
+    }
+
+    pub unsafe fn as_slice_mut<T>(&mut self) -> Result<&mut [T]> {
+        let len = self.ptr.len();
+        let ptr = self.ptr.as_mut_ptr().cast::<T>();
+        let ptr = core::ptr::slice_from_raw_parts_mut(ptr, len / core::mem::size_of::<T>());
+
+        Ok(ptr.as_mut().ok_or(Unhandled)?)
+    }
+
+    pub fn as_fd(&self) -> std::os::fd::BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl Drop for Shm {
+    fn drop(&mut self) {
+        let addr = self.ptr.as_mut_ptr().cast();
+
+        // correctness: `ptr` is always the whole of the mapping
+        let _ = unsafe { nix::sys::mman::munmap(addr, self.ptr.len()) };
+    }
+}
+
+// --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
+
+#[derive(Debug)]
+struct Unhandled;
+
+impl Display for Unhandled {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl Error for Unhandled {}
+
+// --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
+
+#[derive(Debug)]
+struct ReadyGateState {
+    globals: Globals,
+    config: Config,
+    registry: WlRegistry,
+    windows: Vec<(Window, EventQueue<Window>)>,
+    pending: Vec<PendingOutput>,
+}
+
+/// `wl_output` announced after startup, waiting for its `done`
+#[derive(Debug)]
+struct PendingOutput {
+    name: u32,
+    output: WlOutput,
+    xdg_output: Option<ZxdgOutputV1>,
+    info: OutputInfo,
+    done: bool,
+}
+
+impl PendingOutput {
+    fn bind(
+        registry: &WlRegistry,
+        name: u32,
+        version: u32,
+        globals: &Globals,
+        handle: &QueueHandle<ReadyGateState>,
+    ) -> Self {
+        let output = registry.bind(name, version, handle, ());
+        let xdg_output = globals
+            .xdg_output_manager
+            .as_ref()
+            .map(|m| m.get_xdg_output(&output, handle, ()));
+
+        Self {
+            name,
+            output,
+            xdg_output,
+            info: OutputInfo::default(),
+            done: false,
+        }
+    }
+
+    /// `done` of `wl_output` may come before the events of `zxdg_output_v1`
+    fn is_ready(&self) -> bool {
+        self.done && (self.xdg_output.is_none() || self.info.logical.is_some())
+    }
+}
+
+impl ReadyGateState {
+    fn is_known(&self, name: u32) -> bool {
+        self.windows.iter().any(|(w, _)| w.name == name)
+            || self.pending.iter().any(|p| p.name == name)
+    }
+
+    /// destroys windows closed by the compositor, and waits for their outputs again
+    ///
+    /// the outputs are bound again, so the windows are recreated on the next `done` of them
+    fn reap(&mut self, handle: &QueueHandle<Self>) {
+        let (closed, windows) = self
+            .windows
+            .drain(..)
+            .partition::<Vec<_>, _>(|(w, _)| w.closed);
+
+        self.windows = windows;
+
+        for (w, _) in closed {
+            let name = w.name;
+            let version = w.output.version();
+
+            // releases the old output
+            drop(w);
+
+            let pending = PendingOutput::bind(&self.registry, name, version, &self.globals, handle);
+
+            self.pending.push(pending);
+        }
+    }
+
+    /// creates windows for the pending outputs which are done, returns whether any is created
+    fn spawn(&mut self, connection: &Connection, factory: &WindowFactory) -> Result<bool> {
+        let (done, pending) = self
+            .pending
+            .drain(..)
+            .partition::<Vec<_>, _>(PendingOutput::is_ready);
+
+        self.pending = pending;
+
+        let spawned = !done.is_empty();
+
+        for PendingOutput {
+            name,
+            output,
+            xdg_output,
+            info,
+            ..
+        } in done
+        {
+            if let Some(xdg_output) = xdg_output {
+                xdg_output.destroy();
+            }
+
+            eprintln!("output appeared: {}", info.label());
+
+            let window = Window::new(
+                &self.globals,
+                &self.config,
+                name,
+                output,
+                info,
+                connection,
+                factory,
+            )?;
+
+            self.windows.push(window);
+        }
+
+        Ok(spawned)
+    }
+}
+
+impl Dispatch<WlRegistry, ()> for ReadyGateState {
+    fn event(
+        state: &mut Self,
+        registry: &WlRegistry,
+        event: <WlRegistry as Proxy>::Event,
+        (): &(),
+        _: &Connection,
+        handle: &QueueHandle<Self>,
+    ) {
+        type Event = <WlRegistry as Proxy>::Event;
+
+        match event {
+            Event::Global {
+                name,
+                interface,
+                version,
+            } => {
+                // the new registry announces all of globals again
+                if <WlOutput as Proxy>::interface().name != interface || state.is_known(name) {
+                    return;
+                }
+
+                let pending = PendingOutput::bind(registry, name, version, &state.globals, handle);
+
+                state.pending.push(pending);
+            }
+
+            Event::GlobalRemove { name } => {
+                for (w, _) in state.windows.iter().filter(|(w, _)| w.name == name) {
+                    eprintln!("output disappeared: {}", w.info.label());
+                }
+
+                state.windows.retain(|(w, _)| w.name != name);
+                state.pending.retain(|p| p.name != name);
+            }
+
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Dispatch<WlOutput, ()> for ReadyGateState {
+    fn event(
+        state: &mut Self,
+        output: &WlOutput,
+        event: <WlOutput as Proxy>::Event,
+        (): &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let Some(pending) = state.pending.iter_mut().find(|p| &p.output == output) else {
+            return;
+        };
+
+        if pending.info.update(event) {
+            pending.done = true;
+        }
+    }
+}
+
+impl Dispatch<ZxdgOutputV1, ()> for ReadyGateState {
+    fn event(
+        state: &mut Self,
+        xdg_output: &ZxdgOutputV1,
+        event: <ZxdgOutputV1 as Proxy>::Event,
+        (): &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let Some(pending) = state
+            .pending
+            .iter_mut()
+            .find(|p| p.xdg_output.as_ref() == Some(xdg_output))
+        else {
+            return;
+        };
+
+        if pending.info.update_xdg(event) {
+            pending.done = true;
+        }
+    }
+}
+
+// --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
+
+use serde::Deserialize;
+use std::path::PathBuf;
+
+/// validated contents of `$XDG_CONFIG_HOME/bananar/config.toml`
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Config {
+    placement: Placement,
+    style: Style,
+    modules: ModulesConfig,
+    battery: BatteryConfig,
+    clock: ClockConfig,
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/bananar/config.toml`, or `~/.config/bananar/config.toml`
+    fn path() -> Option<PathBuf> {
+        let base = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        };
+
+        Some(base.join("bananar").join("config.toml"))
+    }
+
+    /// falls back to the defaults if the file doesn't exist
+    fn load() -> Result<Self> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(format!("{}: {e}", path.display()).into()),
+        };
+
+        Self::parse(&text).map_err(|e| format!("{}: {e}", path.display()).into())
+    }
+
+    fn parse(text: &str) -> Result<Self, ConfigError> {
+        let raw = toml::from_str::<RawConfig>(text).map_err(ConfigError::Syntax)?;
+
+        Ok(Self {
+            placement: raw.placement.validate()?,
+            style: raw.style.validate()?,
+            modules: raw.modules.validate()?,
+            battery: raw.battery.validate()?,
+            clock: raw.clock.validate()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Style {
+    /// a `.slint` file exporting the component used instead of the built-in `Main`
+    ui: Option<PathBuf>,
+    font_family: String,
+    font_weight: i32,
+    foreground: slint::Color,
+    background: slint::Color,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            ui: None,
+            font_family: "0xProto".to_owned(),
+            font_weight: 100,
+            foreground: slint::Color::from_rgb_u8(0xff, 0xff, 0xff),
+            background: slint::Color::from_argb_u8(0, 0, 0, 0),
+        }
+    }
+}
+
+impl Style {
+    fn apply(&self, ui: &Ui) {
+        use slint_interpreter::Value;
+
+        let family = slint::SharedString::from(self.font_family.as_str());
+        ui.set("font-family", Value::String(family.clone()), |ui| {
+            ui.set_font_family(family)
+        });
+
+        let weight = self.font_weight;
+        ui.set("font-weight", Value::Number(weight.into()), |ui| {
+            ui.set_font_weight(weight)
+        });
+
+        let foreground = self.foreground;
+        ui.set("foreground", Value::Brush(foreground.into()), |ui| {
+            ui.set_foreground(foreground)
+        });
+
+        let background = self.background;
+        ui.set("background-color", Value::Brush(background.into()), |ui| {
+            ui.set_background_color(background)
+        });
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ClockConfig {
+    /// strftime-style, `%Z` is the abbreviation of the zone
+    format: String,
+    /// names in the system tzdb such as `Asia/Tokyo`, shown besides the local time
+    zones: Vec<String>,
+}
+
+impl Default for ClockConfig {
+    fn default() -> Self {
+        Self {
+            format: "%H:%M".to_owned(),
+            zones: Vec::new(),
+        }
+    }
+}
+
+/// names of modules in each section, in the order of the list
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ModulesConfig {
+    left: Vec<ModuleKind>,
+    center: Vec<ModuleKind>,
+    right: Vec<ModuleKind>,
+}
+
+impl Default for ModulesConfig {
+    fn default() -> Self {
+        Self {
+            left: vec![ModuleKind::Battery],
+            center: Vec::new(),
+            right: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ModuleKind {
+    Battery,
+    Clock,
+}
+
+impl ModuleKind {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "battery" => Some(Self::Battery),
+            "clock" => Some(Self::Clock),
+            _ => None,
+        }
+    }
+
+    fn build(&self, config: &Config) -> Result<Box<dyn Module>> {
+        match self {
+            Self::Battery => Ok(Box::new(BatteryModule::new(&config.battery))),
+            Self::Clock => Ok(Box::new(ClockModule::new(&config.clock)?)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct BatteryConfig {
+    /// the root of sysfs, where `class/power_supply` is enumerated
+    sysfs: PathBuf,
+    interval: Duration,
+    alert: AlertConfig,
+}
+
+impl Default for BatteryConfig {
+    fn default() -> Self {
+        Self {
+            sysfs: PathBuf::from("/sys"),
+            interval: Duration::from_secs(60),
+            alert: AlertConfig::default(),
+        }
+    }
+}
+
+/// thresholds in percent of the combined level while discharging
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct AlertConfig {
+    warning: u8,
+    critical: u8,
+    /// how far above the threshold the level has to recover to leave the alert
+    hysteresis: u8,
+    /// run by `sh -c` once per entering the alert
+    warning_command: Option<String>,
+    critical_command: Option<String>,
+}
+
+impl Default for AlertConfig {
+    fn default() -> Self {
+        Self {
+            warning: 20,
+            critical: 10,
+            hysteresis: 3,
+            warning_command: None,
+            critical_command: None,
+        }
+    }
+}
+
+// the file as written, each of absent keys falls back to the default
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct RawConfig {
+    placement: RawPlacement,
+    style: RawStyle,
+    modules: RawModules,
+    battery: RawBattery,
+    clock: RawClock,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct RawClock {
+    format: Option<String>,
+    zones: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct RawModules {
+    left: Option<Vec<String>>,
+    center: Option<Vec<String>>,
+    right: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct RawPlacement {
+    layer: Option<String>,
+    edge: Option<String>,
+    margin: RawMargin,
+    thickness: Option<i64>,
+    exclusive: Option<bool>,
+    namespace: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct RawMargin {
+    top: Option<i32>,
+    right: Option<i32>,
+    bottom: Option<i32>,
+    left: Option<i32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct RawStyle {
+    ui: Option<PathBuf>,
+    font_family: Option<String>,
+    font_weight: Option<i64>,
+    foreground: Option<String>,
+    background: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct RawBattery {
+    sysfs: Option<PathBuf>,
+    /// in seconds
+    interval: Option<u64>,
+    warning: Option<i64>,
+    critical: Option<i64>,
+    hysteresis: Option<i64>,
+    warning_command: Option<String>,
+    critical_command: Option<String>,
+}
+
+impl RawPlacement {
+    fn validate(self) -> Result<Placement, ConfigError> {
+        let default = Placement::default();
+
+        let layer = match self.layer.as_deref() {
+            None => default.layer,
+            Some("background") => Layer::Background,
+            Some("bottom") => Layer::Bottom,
+            Some("top") => Layer::Top,
+            Some("overlay") => Layer::Overlay,
+            Some(v) => {
+                return Err(ConfigError::invalid(
+                    "placement.layer",
+                    format!("`{v}` is none of background, bottom, top and overlay"),
+                ))
+            }
+        };
+
+        let edge = match self.edge.as_deref() {
+            None => default.edge,
+            Some("top") => Edge::Top,
+            Some("bottom") => Edge::Bottom,
+            Some("left") => Edge::Left,
+            Some("right") => Edge::Right,
+            Some(v) => {
+                return Err(ConfigError::invalid(
+                    "placement.edge",
+                    format!("`{v}` is none of top, bottom, left and right"),
+                ))
+            }
+        };
+
+        let margin = Margin {
+            top: self.margin.top.unwrap_or(default.margin.top),
+            right: self.margin.right.unwrap_or(default.margin.right),
+            bottom: self.margin.bottom.unwrap_or(default.margin.bottom),
+            left: self.margin.left.unwrap_or(default.margin.left),
+        };
+
+        let thickness = match self.thickness {
+            None => default.thickness,
+            Some(v @ 1..=0xffff) => Some(v as usize),
+            Some(v) => {
+                return Err(ConfigError::invalid(
+                    "placement.thickness",
+                    format!("{v} is out of 1..=65535"),
+                ))
+            }
+        };
+
+        let namespace = match self.namespace {
+            None => default.namespace,
+            Some(v) if v.is_empty() => {
+                return Err(ConfigError::invalid("placement.namespace", "is empty"))
+            }
+            Some(v) => v,
+        };
+
+        Ok(Placement {
+            layer,
+            edge,
+            margin,
+            thickness,
+            exclusive: self.exclusive.unwrap_or(default.exclusive),
+            namespace,
+        })
+    }
+}
+
+impl RawStyle {
+    fn validate(self) -> Result<Style, ConfigError> {
+        let default = Style::default();
+
+        let font_weight = match self.font_weight {
+            None => default.font_weight,
+            Some(v @ 1..=1000) => v as i32,
+            Some(v) => {
+                return Err(ConfigError::invalid(
+                    "style.font-weight",
+                    format!("{v} is out of 1..=1000"),
+                ))
+            }
+        };
+
+        let color = |key, value: Option<String>, default| match value {
+            None => Ok(default),
+            Some(v) => parse_color(&v).ok_or_else(|| {
+                ConfigError::invalid(key, format!("`{v}` is neither #rrggbb nor #rrggbbaa"))
+            }),
+        };
+
+        Ok(Style {
+            ui: self.ui.or(default.ui),
+            font_family: self.font_family.unwrap_or(default.font_family),
+            font_weight,
+            foreground: color("style.foreground", self.foreground, default.foreground)?,
+            background: color("style.background", self.background, default.background)?,
+        })
+    }
+}
+
+impl RawClock {
+    fn validate(self) -> Result<ClockConfig, ConfigError> {
+        use chrono::format::Item;
+        use chrono::format::StrftimeItems;
+
+        let default = ClockConfig::default();
+
+        let format = match self.format {
+            None => default.format,
+            Some(v) if StrftimeItems::new(&v).any(|i| matches!(i, Item::Error)) => {
+                return Err(ConfigError::invalid(
+                    "clock.format",
+                    format!("`{v}` has an unknown specifier"),
+                ))
+            }
+            Some(v) => v,
+        };
+
+        let zones = self.zones.unwrap_or(default.zones);
+
+        if let Some(zone) = zones
+            .iter()
+            .find(|z| tz::TimeZone::from_posix_tz(z).is_err())
+        {
+            return Err(ConfigError::invalid(
+                "clock.zones",
+                format!("`{zone}` is not found in the tzdb"),
+            ));
+        }
 
-    fn from_rgba8888(value: Pixel) -> Pixel {
-        value
+        Ok(ClockConfig { format, zones })
     }
+}
 
-    fn from_argb8888(value: Pixel) -> Pixel {
-        let Pixel {
-            red: alpha,
-            green: red,
-            blue: green,
-            alpha: blue,
-        } = value;
+impl RawModules {
+    fn validate(self) -> Result<ModulesConfig, ConfigError> {
+        let default = ModulesConfig::default();
+
+        let kinds = |key, names: Option<Vec<String>>, default| match names {
+            None => Ok(default),
+            Some(names) => names
+                .iter()
+                .map(|n| {
+                    ModuleKind::parse(n)
+                        .ok_or_else(|| ConfigError::invalid(key, format!("`{n}` is not a module")))
+                })
+                .collect(),
+        };
 
-        Pixel {
-            red,
-            green,
-            blue,
-            alpha,
-        }
+        Ok(ModulesConfig {
+            left: kinds("modules.left", self.left, default.left)?,
+            center: kinds("modules.center", self.center, default.center)?,
+            right: kinds("modules.right", self.right, default.right)?,
+        })
     }
 }
 
-// --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
+impl RawBattery {
+    fn validate(self) -> Result<BatteryConfig, ConfigError> {
+        let default = BatteryConfig::default();
 
-use wayland_client::protocol::wl_registry::WlRegistry;
-use wayland_client::Dispatch;
-use wayland_client::Proxy;
-use wayland_client::QueueHandle;
+        let interval = match self.interval {
+            None => default.interval,
+            Some(0) => return Err(ConfigError::invalid("battery.interval", "is zero")),
+            Some(v) => Duration::from_secs(v),
+        };
 
-impl Dispatch<WlRegistry, ()> for InitialGateState {
-    fn event(
-        state: &mut Self,
-        registry: &WlRegistry,
-        event: <WlRegistry as Proxy>::Event,
-        (): &(),
-        _: &Connection,
-        handle: &QueueHandle<Self>,
-    ) {
-        type Event = <WlRegistry as Proxy>::Event;
+        let percent = |key, value: Option<i64>, default| match value {
+            None => Ok(default),
+            Some(v @ 0..=100) => Ok(v as u8),
+            Some(v) => Err(ConfigError::invalid(key, format!("{v} is out of 0..=100"))),
+        };
 
-        let Event::Global {
-            name,
-            version,
-            interface,
-        } = event
-        else {
-            unreachable!()
+        let alert = AlertConfig {
+            warning: percent("battery.warning", self.warning, default.alert.warning)?,
+            critical: percent("battery.critical", self.critical, default.alert.critical)?,
+            hysteresis: percent(
+                "battery.hysteresis",
+                self.hysteresis,
+                default.alert.hysteresis,
+            )?,
+            warning_command: self.warning_command.or(default.alert.warning_command),
+            critical_command: self.critical_command.or(default.alert.critical_command),
         };
 
-        if <WlCompositor as Proxy>::interface().name == interface {
-            let None = state
-                .compositor
-                .replace(registry.bind(name, version, handle, ()))
-            else {
-                unreachable!()
-            };
+        if alert.critical > alert.warning {
+            return Err(ConfigError::invalid(
+                "battery.critical",
+                format!("{} is above `battery.warning`", alert.critical),
+            ));
         }
 
-        if <WlShm as Proxy>::interface().name == interface {
-            let None = state.shm.replace(registry.bind(name, version, handle, ())) else {
-                unreachable!()
-            };
+        // otherwise leaving the critical would skip the warning
+        if alert.critical + alert.hysteresis > alert.warning {
+            return Err(ConfigError::invalid(
+                "battery.hysteresis",
+                format!(
+                    "{} is above the gap between `battery.critical` and `battery.warning`",
+                    alert.hysteresis
+                ),
+            ));
         }
 
-        if <ZwlrLayerShellV1 as Proxy>::interface().name == interface {
-            let None = state
-                .layer_shell
-                .replace(registry.bind(name, version, handle, ()))
-            else {
-                unreachable!()
-            };
+        Ok(BatteryConfig {
+            sysfs: self.sysfs.unwrap_or(default.sysfs),
+            interval,
+            alert,
+        })
+    }
+}
+
+/// `#rrggbb` or `#rrggbbaa`
+fn parse_color(value: &str) -> Option<slint::Color> {
+    let hex = value.strip_prefix('#')?;
+
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let v = u32::from_str_radix(hex, 16).ok()?;
+
+    match hex.len() {
+        6 => Some(slint::Color::from_rgb_u8(
+            (v >> 16) as u8,
+            (v >> 8) as u8,
+            v as u8,
+        )),
+        8 => Some(slint::Color::from_argb_u8(
+            v as u8,
+            (v >> 24) as u8,
+            (v >> 16) as u8,
+            (v >> 8) as u8,
+        )),
+        _ => None,
+    }
+}
+
+#[derive(Debug)]
+enum ConfigError {
+    Syntax(toml::de::Error),
+    Invalid { key: &'static str, reason: String },
+}
+
+impl ConfigError {
+    fn invalid(key: &'static str, reason: impl Into<String>) -> Self {
+        Self::Invalid {
+            key,
+            reason: reason.into(),
         }
+    }
+}
 
-        if <WlOutput as Proxy>::interface().name == interface {
-            state
-                .outputs
-                .push(LazyBind::new(registry.clone(), name, version));
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Syntax(e) => write!(f, "{e}"),
+            Self::Invalid { key, reason } => write!(f, "invalid `{key}`: {reason}"),
         }
     }
 }
 
-// `WlCompositor` has no events
-wayland_client::delegate_noop!(InitialGateState: WlCompositor);
+impl Error for ConfigError {}
 
-impl Dispatch<WlShm, ()> for InitialGateState {
-    fn event(
-        state: &mut Self,
-        _: &WlShm,
-        event: <WlShm as Proxy>::Event,
-        (): &(),
-        _: &Connection,
-        _: &QueueHandle<Self>,
-    ) {
-        type Event = <WlShm as Proxy>::Event;
+use nix::sys::inotify::AddWatchFlags;
+use nix::sys::inotify::Inotify;
+use std::ffi::OsString;
 
-        let Event::Format { format } = event else {
-            unreachable!()
+/// watches the directory of the config file, since editors replace the file rather than write it
+#[derive(Debug)]
+struct ConfigWatcher {
+    inotify: Inotify,
+    name: OsString,
+}
+
+impl ConfigWatcher {
+    fn new(path: &Path) -> Result<Self> {
+        use nix::sys::inotify::InitFlags;
+
+        let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+            return Err(MissingError::new("directory of the config").into());
         };
 
-        use wayland_client::protocol::wl_shm::Format;
-        match format.into_result() {
-            Ok(Format::Rgba8888) => state.pp = Some(PixelProxy::Rgba8888),
-            Ok(Format::Argb8888) => state.pp = Some(PixelProxy::Argb8888),
+        let inotify = Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC)?;
 
-            _ => (),
+        let mask = AddWatchFlags::IN_CLOSE_WRITE
+            | AddWatchFlags::IN_MOVED_TO
+            | AddWatchFlags::IN_MOVED_FROM
+            | AddWatchFlags::IN_DELETE;
+        inotify.add_watch(dir, mask)?;
+
+        Ok(Self {
+            inotify,
+            name: name.to_owned(),
+        })
+    }
+
+    /// to be called when readable, returns whether the config file is touched
+    fn changed(&self) -> Result<bool> {
+        let events = match self.inotify.read_events() {
+            Ok(events) => events,
+            Err(nix::errno::Errno::EAGAIN) => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(events.iter().any(|e| e.name.as_ref() == Some(&self.name)))
+    }
+}
+
+impl AsFd for ConfigWatcher {
+    fn as_fd(&self) -> std::os::fd::BorrowedFd<'_> {
+        self.inotify.as_fd()
+    }
+}
+
+// --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
+
+use wayland_protocols_wlr::layer_shell::v1::client::zwlr_layer_shell_v1::Layer;
+use wayland_protocols_wlr::layer_shell::v1::client::zwlr_layer_surface_v1::Anchor;
+
+/// where and how bars are placed on each output
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Placement {
+    layer: Layer,
+    edge: Edge,
+    margin: Margin,
+    /// height of horizontal bars or width of vertical ones in logical pixels, 1/64 of the output if `None`
+    thickness: Option<usize>,
+    /// whether other surfaces avoid the bar
+    exclusive: bool,
+    /// for the rules of the compositor
+    namespace: String,
+}
+
+impl Default for Placement {
+    fn default() -> Self {
+        Self {
+            layer: Layer::Background,
+            edge: Edge::Top,
+            margin: Margin::default(),
+            thickness: None,
+            exclusive: true,
+            namespace: "bananar".to_owned(),
         }
     }
 }
 
-// `ZwlrLayerShellV1` has no events
-wayland_client::delegate_noop!(InitialGateState: ZwlrLayerShellV1);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
 
-// unallow to receive events
-wayland_client::delegate_noop!(InitialGateState: WlOutput);
+impl Edge {
+    fn is_vertical(&self) -> bool {
+        matches!(self, Self::Left | Self::Right)
+    }
+
+    /// the edge and both of its ends
+    fn anchor(&self) -> Anchor {
+        match self {
+            Self::Top => Anchor::Top | Anchor::Left | Anchor::Right,
+            Self::Bottom => Anchor::Bottom | Anchor::Left | Anchor::Right,
+            Self::Left => Anchor::Left | Anchor::Top | Anchor::Bottom,
+            Self::Right => Anchor::Right | Anchor::Top | Anchor::Bottom,
+        }
+    }
+}
+
+/// in logical pixels
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Margin {
+    top: i32,
+    right: i32,
+    bottom: i32,
+    left: i32,
+}
 
 // --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
 
-use std::collections::HashMap;
-use wayland_client::backend::ObjectId;
+use wayland_client::protocol::wl_buffer::WlBuffer;
+use wayland_client::protocol::wl_callback::WlCallback;
+use wayland_client::protocol::wl_surface::WlSurface;
+use wayland_client::EventQueue;
+use wayland_protocols_wlr::layer_shell::v1::client::zwlr_layer_surface_v1::ZwlrLayerSurfaceV1;
 
 #[derive(Debug)]
-struct PrepareGateState {
-    compositor: WlCompositor,
+struct Window {
+    name: u32,
+    output: WlOutput,
+    info: OutputInfo,
+    surface: WlSurface,
+    layer_surface: ZwlrLayerSurfaceV1,
     shm: WlShm,
-    layer_shell: ZwlrLayerShellV1,
-    outputs: Vec<WlOutput>,
-    modes: HashMap<ObjectId, Mode>,
+    requested: Size,
+    configured: Option<Size>,
+    scale: Scale,
+    fractional: Option<Fractional>,
+    transform: Transform,
+    dirty: bool,
+    buffers: Option<BufferPool>,
+    /// replaced ones still held by the compositor, dropped on their last `release`
+    retired: Vec<BufferPool>,
+    frame: Option<WlCallback>,
+    closed: bool,
     pp: PixelProxy,
+    /// unrotated frame, used only if `transform` is not `Normal`
+    scratch: Vec<Pixel>,
+    view: View,
 }
 
-use wayland_client::protocol::wl_shm_pool::WlShmPool;
+impl Window {
+    fn new(
+        globals: &Globals,
+        config: &Config,
+        name: u32,
+        output: WlOutput,
+        info: OutputInfo,
+        connection: &Connection,
+        factory: &WindowFactory,
+    ) -> Result<(Self, EventQueue<Self>)> {
+        let placement = &config.placement;
+
+        let mut queue = connection.new_event_queue();
+        let handle = &queue.handle();
+
+        // surface-local, in logical pixels
+        let output_size = info
+            .logical_size()
+            .ok_or_else(|| MissingError::new("size of wl_output"))?;
+
+        // the same as horizontal bars by default
+        let thickness = placement.thickness.unwrap_or(output_size.height / 64);
+
+        let requested = match placement.edge.is_vertical() {
+            true => Size {
+                width: thickness,
+                height: output_size.height,
+            },
+            false => Size {
+                width: output_size.width,
+                height: thickness,
+            },
+        };
 
-impl PrepareGateState {
-    fn forward(mut self, connection: &Connection) -> Result<ReadyGateState> {
-        let pp = self.pp;
-
-        let create_buffer = |mode: &Mode, qh: &QueueHandle<Window>| -> Result<_> {
-            use wayland_client::protocol::wl_shm::Format;
-            let format = match pp {
-                PixelProxy::Rgba8888 => Format::Rgba8888,
-                PixelProxy::Argb8888 => Format::Argb8888,
-            };
+        let scale = info.scale.max(1);
+
+        let surface = globals.compositor.create_surface(handle, ());
+        let layer_surface = globals.layer_shell.get_layer_surface(
+            &surface,
+            Some(&output),
+            placement.layer,
+            placement.namespace.clone(),
+            handle,
+            (),
+        );
+
+        // stretched along the edge by the compositor, so that the margins are respected
+        match placement.edge.is_vertical() {
+            true => layer_surface.set_size(thickness as u32, 0),
+            false => layer_surface.set_size(0, thickness as u32),
+        }
+
+        layer_surface.set_anchor(placement.edge.anchor());
 
-            let pixel_size = 4;
+        let Margin {
+            top,
+            right,
+            bottom,
+            left,
+        } = placement.margin;
+        layer_surface.set_margin(top, right, bottom, left);
 
-            let size = mode.width * mode.height * pixel_size;
-            let raw = Shm::new(size)?;
+        match placement.exclusive {
+            true => layer_surface.set_exclusive_zone(thickness as i32),
+            false => layer_surface.set_exclusive_zone(0),
+        }
 
-            let pool = {
-                struct Stub;
+        let fractional = globals
+            .fractional
+            .as_ref()
+            .map(|(manager, viewporter)| Fractional {
+                scale: manager.get_fractional_scale(&surface, handle, ()),
+                viewport: viewporter.get_viewport(&surface, handle, ()),
+            });
 
-                // correctness: `wl_shm_pool` has no events
-                wayland_client::delegate_noop!(Stub: WlShmPool);
-                let qh = connection.new_event_queue::<Stub>().handle();
+        surface.commit();
 
-                let size = size.try_into()?;
+        let view = factory.create()?;
+        config.style.apply(&view.ui);
 
-                self.shm.create_pool(raw.as_fd(), size, &qh, ())
-            };
+        let vertical = placement.edge.is_vertical();
+        view.ui
+            .set("vertical", slint_interpreter::Value::Bool(vertical), |ui| {
+                ui.set_vertical(vertical)
+            });
+        view.ui.show()?;
 
-            let buffer = {
-                let w = mode.width.try_into()?;
-                let h = mode.height.try_into()?;
-                let s = (mode.width * pixel_size).try_into()?;
+        let mut window = Self {
+            name,
+            output,
+            info,
+            surface,
+            layer_surface,
+            shm: globals.shm.clone(),
+            requested,
+            configured: None,
+            scale: Scale::from_integer(scale),
+            fractional,
+            transform: Transform::Normal,
+            dirty: false,
+            buffers: None,
+            retired: Vec::new(),
+            frame: None,
+            closed: false,
+            pp: globals.pp,
+            scratch: Vec::new(),
+            view,
+        };
 
-                pool.create_buffer(0, w, h, s, format, qh, ())
-            };
+        queue.roundtrip(&mut window)?;
+
+        window.reconfigure(connection, handle)?;
 
-            Ok((buffer, raw))
+        queue.roundtrip(&mut window)?;
+
+        Ok((window, queue))
+    }
+
+    /// applies the size of the last `configure` and the scale, reallocates the buffers if changed
+    fn reconfigure(&mut self, connection: &Connection, qh: &QueueHandle<Self>) -> Result {
+        if !core::mem::take(&mut self.dirty) {
+            return Ok(());
+        }
+
+        // waits for the first `configure`
+        let Some(logical) = self.configured else {
+            return Ok(());
         };
 
-        use wayland_protocols_wlr::layer_shell::v1::client::zwlr_layer_shell_v1::Layer;
-        let layer = Layer::Background;
+        let size = self.scale.apply(logical);
+        let buffer_size = size.transform(self.transform);
+
+        match &mut self.buffers {
+            Some(buffers) if buffers.size == buffer_size => (),
+            // the compositor may still read the busy ones, so leave them in the old memory
+            Some(buffers) if buffers.is_busy() => {
+                let new = BufferPool::new(&self.shm, self.pp, buffer_size, connection, qh)?;
+                self.retired.push(core::mem::replace(buffers, new));
+            }
+            Some(buffers) => buffers.resize(buffer_size, qh)?,
+            None => {
+                let buffers = BufferPool::new(&self.shm, self.pp, buffer_size, connection, qh)?;
+                self.buffers = Some(buffers);
+            }
+        }
 
-        let namespace = "namespace";
+        // applied with the next commit
+        match &self.fractional {
+            Some(f) => f
+                .viewport
+                .set_destination(logical.width.try_into()?, logical.height.try_into()?),
+            None => self.surface.set_buffer_scale(self.scale.as_integer()),
+        }
 
-        use wayland_protocols_wlr::layer_shell::v1::client::zwlr_layer_surface_v1::Anchor;
-        let anchor = Anchor::Top;
+        // `preferred_buffer_transform` is sent only since v6, so older ones stay `Normal`
+        if self.surface.version() >= 2 {
+            self.surface.set_buffer_transform(self.transform);
+        }
 
-        let windows = self
-            .outputs
-            .into_iter()
-            .map(|p| {
-                self.modes
-                    .remove(&p.id())
-                    .ok_or_else(|| MissingError::new("mode of wl_output"))
-                    .map(|m| (p, m))
-            })
-            .try_collect::<Vec<_>>()?
-            .into_iter()
-            .map(|(output, mode)| -> Result<_> {
-                let mut queue = connection.new_event_queue();
-                let handle = &queue.handle();
-
-                let surface = self.compositor.create_surface(handle, ());
-                let layer_surface = self.layer_shell.get_layer_surface(
-                    &surface,
-                    Some(&output),
-                    layer,
-                    namespace.to_owned(),
-                    handle,
-                    (),
-                );
+        self.view.resize(size, self.scale.as_factor())
+    }
 
-                layer_surface.set_size(0, 0);
-                layer_surface.set_anchor(anchor);
-                layer_surface.set_exclusive_zone(0);
-                surface.commit();
+    /// draws if needed, throttled by `wl_surface.frame` until the compositor wants a new frame
+    fn render(&mut self, qh: &QueueHandle<Self>) -> Result {
+        if self.frame.is_some() {
+            return Ok(());
+        }
 
-                let (buffer, raw) = create_buffer(&mode, handle)?;
+        // the compositor may be still reading others, so render only into released one
+        let Some((buffers, index)) = self
+            .buffers
+            .as_mut()
+            .and_then(|b| b.acquire().map(|i| (b, i)))
+        else {
+            return Ok(());
+        };
 
-                let mut window = Window {
-                    output,
-                    mode,
-                    surface,
-                    layer_surface,
-                    buffer,
-                    raw,
-                    pp,
-                };
+        let transform = self.transform;
+        let size = buffers.size;
+        let pixels = buffers.pixels(index)?;
+        let scratch = &mut self.scratch;
+        let conv = self.pp.as_converter();
 
-                queue.roundtrip(&mut window)?;
+        let drawn = self.view.window.draw_if_needed(|r| match transform {
+            Transform::Normal => {
+                r.render(pixels, size.width);
 
-                window.surface.attach(Some(&window.buffer), 0, 0);
-                window.surface.commit();
+                for e in pixels {
+                    *e = conv(*e);
+                }
+            }
 
-                queue.roundtrip(&mut window)?;
+            // the buffer is already transformed, so render unrotated once and copy into it
+            _ => {
+                let size = size.transform(transform);
+                scratch.resize(size.width * size.height, Pixel::default());
 
-                Ok((window, queue))
-            })
-            .try_collect()?;
+                r.render(scratch, size.width);
+
+                copy_transformed(scratch, size, pixels, transform, conv);
+            }
+        });
+
+        if !drawn {
+            return Ok(());
+        }
+
+        buffers.attach(index, &self.surface);
+        self.surface.damage(0, 0, i32::MAX, i32::MAX);
+        self.frame = Some(self.surface.frame(qh, ()));
+        self.surface.commit();
+
+        Ok(())
+    }
+}
+
+impl Drop for Window {
+    fn drop(&mut self) {
+        let _ = self.view.ui.hide();
 
-        Ok(ReadyGateState { windows })
+        // the role object and the extensions have to be destroyed before its surface
+        self.buffers = None;
+        self.layer_surface.destroy();
+
+        if let Some(f) = &self.fractional {
+            f.scale.destroy();
+            f.viewport.destroy();
+        }
+
+        self.surface.destroy();
+
+        if self.output.version() >= 3 {
+            self.output.release();
+        }
     }
 }
 
 // --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
 
+use wayland_protocols::wp::fractional_scale::v1::client::wp_fractional_scale_v1::WpFractionalScaleV1;
+use wayland_protocols::wp::viewporter::client::wp_viewport::WpViewport;
+
 #[derive(Debug)]
-struct Mode {
-    width: usize,
-    height: usize,
+struct Fractional {
+    scale: WpFractionalScaleV1,
+    viewport: WpViewport,
 }
 
-// --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
+/// scale in 120ths, the same as `wp_fractional_scale_v1`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Scale(u32);
 
-// unallow to receive events
-wayland_client::delegate_noop!(PrepareGateState: WlRegistry);
-wayland_client::delegate_noop!(PrepareGateState: WlCompositor);
-wayland_client::delegate_noop!(PrepareGateState: WlShm);
-wayland_client::delegate_noop!(PrepareGateState: ZwlrLayerShellV1);
+impl Scale {
+    const DENOMINATOR: u32 = 120;
 
-impl Dispatch<WlOutput, ()> for PrepareGateState {
-    fn event(
-        state: &mut Self,
-        output: &WlOutput,
-        event: <WlOutput as Proxy>::Event,
-        (): &(),
-        _: &Connection,
-        _: &QueueHandle<Self>,
-    ) {
-        type Event = <WlOutput as Proxy>::Event;
+    fn from_integer(factor: i32) -> Self {
+        Self(factor.max(1) as u32 * Self::DENOMINATOR)
+    }
 
-        match event {
-            Event::Mode { width, height, .. } => {
-                let id = output.id();
-                let mode = Mode {
-                    width: width as usize,
-                    height: height as usize,
-                };
+    /// rounds down, for `wl_surface.set_buffer_scale`
+    fn as_integer(&self) -> i32 {
+        (self.0 / Self::DENOMINATOR).max(1) as i32
+    }
 
-                let None = state.modes.insert(id, mode) else {
-                    unreachable!()
-                };
-            }
+    fn as_factor(&self) -> f32 {
+        self.0 as f32 / Self::DENOMINATOR as f32
+    }
 
-            Event::Geometry { .. }
-            | Event::Done
-            | Event::Scale { .. }
-            | Event::Name { .. }
-            | Event::Description { .. } => (),
+    /// converts the logical size into the size of buffer, rounding halfway away from zero
+    fn apply(&self, logical: Size) -> Size {
+        let scale = |v: usize| {
+            (v * self.0 as usize + Self::DENOMINATOR as usize / 2) / Self::DENOMINATOR as usize
+        };
 
-            _ => unreachable!(),
+        Size {
+            width: scale(logical.width),
+            height: scale(logical.height),
+        }
+    }
+}
+
+// --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
+
+use wayland_client::protocol::wl_output::Transform;
+
+fn is_transposed(transform: Transform) -> bool {
+    matches!(
+        transform,
+        Transform::_90 | Transform::_270 | Transform::Flipped90 | Transform::Flipped270
+    )
+}
+
+/// copies `src` of `size` into `dst` as the content transformed by `wl_surface.set_buffer_transform`
+///
+/// the buffer is the inverse of `transform` applied onto the surface, so 90 and 270 are swapped
+fn copy_transformed(
+    src: &[Pixel],
+    size: Size,
+    dst: &mut [Pixel],
+    transform: Transform,
+    conv: fn(Pixel) -> Pixel,
+) {
+    let Size { width, height } = size;
+    let stride = size.transform(transform).width;
+
+    for y in 0..height {
+        for x in 0..width {
+            let (bx, by) = match transform {
+                Transform::_90 => (y, width - 1 - x),
+                Transform::_180 => (width - 1 - x, height - 1 - y),
+                Transform::_270 => (height - 1 - y, x),
+                Transform::Flipped => (width - 1 - x, y),
+                Transform::Flipped90 => (y, x),
+                Transform::Flipped180 => (x, height - 1 - y),
+                Transform::Flipped270 => (height - 1 - y, width - 1 - x),
+                _ => (x, y),
+            };
+
+            dst[by * stride + bx] = conv(src[y * width + x]);
         }
     }
 }
 
 // --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
 
-use core::ptr::NonNull;
-use std::os::fd::AsFd;
-use std::os::fd::OwnedFd;
+use wayland_client::protocol::wl_shm::Format;
+use wayland_client::protocol::wl_shm_pool::WlShmPool;
+
+const PIXEL_SIZE: usize = 4;
+const BUFFER_COUNT: usize = 2;
 
 #[derive(Debug)]
-struct Shm {
-    ptr: NonNull<[u8]>,
-    fd: OwnedFd,
+struct BufferSlot {
+    buffer: WlBuffer,
+    busy: bool,
 }
 
-impl Shm {
-    pub fn new(size: usize) -> Result<Self> {
-        let fd = Self::open()?;
-
-        let ptr = Self::mmap(&fd, size)?;
-        let ptr = core::ptr::slice_from_raw_parts_mut(ptr, size);
-        let ptr = NonNull::new(ptr).ok_or(Unhandled)?;
+/// buffers sharing one `wl_shm_pool`, each of them placed at `index * frame_len`
+#[derive(Debug)]
+struct BufferPool {
+    slots: Vec<BufferSlot>,
+    pool: WlShmPool,
+    raw: Shm,
+    format: Format,
+    size: Size,
+}
 
-        Ok(Self { ptr, fd })
-    }
+impl BufferPool {
+    fn new(
+        shm: &WlShm,
+        pp: PixelProxy,
+        size: Size,
+        connection: &Connection,
+        qh: &QueueHandle<Window>,
+    ) -> Result<Self> {
+        let format = match pp {
+            PixelProxy::Rgba8888 => Format::Rgba8888,
+            PixelProxy::Argb8888 => Format::Argb8888,
+        };
 
-    fn open() -> Result<OwnedFd> {
-        let name = {
-            let ts = std::time::SystemTime::now().elapsed().unwrap().as_nanos();
+        let len = Self::frame_len(size) * BUFFER_COUNT;
+        let raw = Shm::new(len)?;
 
-            format!("/wl-shm-{ts:32x}")
-        };
+        let pool = {
+            struct Stub;
 
-        let fd = {
-            use nix::fcntl::OFlag;
-            use nix::sys::stat::Mode;
+            // correctness: `wl_shm_pool` has no events
+            wayland_client::delegate_noop!(Stub: WlShmPool);
+            let qh = connection.new_event_queue::<Stub>().handle();
 
-            let flag = OFlag::O_RDWR | OFlag::O_CREAT | OFlag::O_EXCL;
-            let mode = Mode::S_IRUSR | Mode::S_IWUSR | Mode::S_IXUSR;
+            let len = len.try_into()?;
 
-            nix::sys::mman::shm_open(&*name, flag, mode)?
+            shm.create_pool(raw.as_fd(), len, &qh, ())
         };
 
-        nix::sys::mman::shm_unlink(&*name)?;
+        let slots = Self::create_slots(&pool, format, size, qh)?;
 
-        Ok(fd)
+        Ok(Self {
+            slots,
+            pool,
+            raw,
+            format,
+            size,
+        })
     }
 
-    fn mmap<F: AsFd>(fd: F, size: usize) -> Result<*mut u8> {
-        nix::unistd::ftruncate(&fd, size.try_into()?)?;
-
-        let ptr = {
-            use nix::sys::mman::MapFlags;
-            use nix::sys::mman::ProtFlags;
-
-            let pflag = ProtFlags::PROT_READ | ProtFlags::PROT_WRITE;
-            let mflag = MapFlags::MAP_SHARED;
-
-            unsafe { nix::sys::mman::mmap(None, size.try_into()?, pflag, mflag, Some(&fd), 0) }?
-        };
+    fn frame_len(size: Size) -> usize {
+        size.width * size.height * PIXEL_SIZE
+    }
 
-        Ok(ptr.cast::<u8>())
+    fn create_slots(
+        pool: &WlShmPool,
+        format: Format,
+        size: Size,
+        qh: &QueueHandle<Window>,
+    ) -> Result<Vec<BufferSlot>> {
+        (0..BUFFER_COUNT)
+            .map(|i| -> Result<_> {
+                let o = (i * Self::frame_len(size)).try_into()?;
+                let w = size.width.try_into()?;
+                let h = size.height.try_into()?;
+                let s = (size.width * PIXEL_SIZE).try_into()?;
+
+                let buffer = pool.create_buffer(o, w, h, s, format, qh, ());
+
+                Ok(BufferSlot {
+                    buffer,
+                    busy: false,
+                })
+            })
+            .try_collect()
     }
 
-    pub fn resize(&mut self, size: usize) -> Result<()> {
-        nix::unistd::ftruncate(&self.fd, size.try_into()?)?;
+    /// remaps the memory, grows the pool if needed, and recreates the buffers
+    ///
+    /// all the buffers must be released, as their memory is reused
+    fn resize(&mut self, size: Size, qh: &QueueHandle<Window>) -> Result {
+        let len = Self::frame_len(size) * BUFFER_COUNT;
 
-        let ptr = self.ptr.as_mut_ptr();
-        let ptr = core::ptr::slice_from_raw_parts_mut(ptr, size);
-        let ptr = NonNull::new(ptr).ok_or(Unhandled)?;
+        // `wl_shm_pool` can only grow, so the pool is left as is on shrinking
+        if self.raw.len() < len {
+            self.raw.resize(len)?;
+            self.pool.resize(len.try_into()?);
+        }
 
-        self.ptr = ptr;
+        self.slots.drain(..).for_each(|s| s.buffer.destroy());
+        self.slots = Self::create_slots(&self.pool, self.format, size, qh)?;
+        self.size = size;
 
         Ok(())
     }
 
-    pub unsafe fn as_slice_mut<T>(&mut self) -> Result<&mut [T]> {
-        let len = self.ptr.len();
-        let ptr = self.ptr.as_mut_ptr().cast::<T>();
-        let ptr = core::ptr::slice_from_raw_parts_mut(ptr, len / core::mem::size_of::<T>());
-
-        Ok(ptr.as_mut().ok_or(Unhandled)?)
+    fn is_busy(&self) -> bool {
+        self.slots.iter().any(|s| s.busy)
     }
 
-    pub fn as_fd(&self) -> std::os::fd::BorrowedFd<'_> {
-        self.fd.as_fd()
+    /// returns the index of a buffer which is not held by the compositor
+    fn acquire(&self) -> Option<usize> {
+        self.slots.iter().position(|s| !s.busy)
     }
-}
-
-// --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
 
-#[derive(Debug)]
-struct Unhandled;
+    fn pixels(&mut self, index: usize) -> Result<&mut [Pixel]> {
+        let len = self.size.width * self.size.height;
+        let pixels = unsafe { self.raw.as_slice_mut::<Pixel>() }?;
 
-impl Display for Unhandled {
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        write!(f, "{self:?}")
+        pixels
+            .get_mut(index * len..(index + 1) * len)
+            .ok_or_else(|| Unhandled.into())
     }
-}
 
-impl Error for Unhandled {}
+    /// attaches the buffer to the surface, and keeps it until `release`
+    fn attach(&mut self, index: usize, surface: &WlSurface) {
+        let slot = &mut self.slots[index];
 
-// --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
+        surface.attach(Some(&slot.buffer), 0, 0);
+        slot.busy = true;
+    }
 
-#[derive(Debug)]
-struct ReadyGateState {
-    windows: Vec<(Window, EventQueue<Window>)>,
+    fn release(&mut self, buffer: &WlBuffer) {
+        if let Some(slot) = self.slots.iter_mut().find(|s| &s.buffer == buffer) {
+            slot.busy = false;
+        }
+    }
 }
 
-// --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
-
-use wayland_client::protocol::wl_buffer::WlBuffer;
-use wayland_client::protocol::wl_surface::WlSurface;
-use wayland_client::EventQueue;
-use wayland_protocols_wlr::layer_shell::v1::client::zwlr_layer_surface_v1::ZwlrLayerSurfaceV1;
-
-#[derive(Debug)]
-struct Window {
-    output: WlOutput,
-    mode: Mode,
-    surface: WlSurface,
-    layer_surface: ZwlrLayerSurfaceV1,
-    buffer: WlBuffer,
-    raw: Shm,
-    pp: PixelProxy,
+impl Drop for BufferPool {
+    fn drop(&mut self) {
+        self.slots.iter().for_each(|s| s.buffer.destroy());
+        self.pool.destroy();
+    }
 }
 
 // --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
@@ -747,21 +3797,29 @@ impl Dispatch<WlSurface, ()> for Window {
                 }
             }
 
-            Event::Leave { output } => {
-                if output.id() != state.output.id() {
-                    panic!();
-                }
+            // the output is going away, the window is destroyed by `global_remove`
+            Event::Leave { .. } => (),
+
+            // `wp_fractional_scale_v1` has priority if exists
+            Event::PreferredBufferScale { factor } if state.fractional.is_none() => {
+                let scale = Scale::from_integer(factor);
 
-                unimplemented!()
+                if state.scale != scale {
+                    state.scale = scale;
+                    state.dirty = true;
+                }
             }
 
-            Event::PreferredBufferScale { .. } => {
-                dbg!(&event);
-            },
+            Event::PreferredBufferScale { .. } => (),
 
-            Event::PreferredBufferTransform { .. } => {
-                dbg!(&event);
-            },
+            Event::PreferredBufferTransform { transform } => {
+                let transform = transform.into_result().unwrap_or(Transform::Normal);
+
+                if state.transform != transform {
+                    state.transform = transform;
+                    state.dirty = true;
+                }
+            }
 
             _ => unreachable!(),
         }
@@ -770,8 +3828,8 @@ impl Dispatch<WlSurface, ()> for Window {
 
 impl Dispatch<WlBuffer, ()> for Window {
     fn event(
-        _: &mut Self,
-        _: &WlBuffer,
+        state: &mut Self,
+        buffer: &WlBuffer,
         event: <WlBuffer as Proxy>::Event,
         (): &(),
         _: &Connection,
@@ -781,7 +3839,61 @@ impl Dispatch<WlBuffer, ()> for Window {
 
         match event {
             Event::Release => {
-                unimplemented!()
+                if let Some(buffers) = &mut state.buffers {
+                    buffers.release(buffer);
+                }
+
+                state.retired.iter_mut().for_each(|b| b.release(buffer));
+                state.retired.retain(BufferPool::is_busy);
+            }
+
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Dispatch<WlCallback, ()> for Window {
+    fn event(
+        state: &mut Self,
+        callback: &WlCallback,
+        event: <WlCallback as Proxy>::Event,
+        (): &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        type Event = <WlCallback as Proxy>::Event;
+
+        match event {
+            Event::Done { .. } => {
+                if state.frame.as_ref() == Some(callback) {
+                    state.frame = None;
+                }
+            }
+
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Dispatch<WpFractionalScaleV1, ()> for Window {
+    fn event(
+        state: &mut Self,
+        _: &WpFractionalScaleV1,
+        event: <WpFractionalScaleV1 as Proxy>::Event,
+        (): &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        type Event = <WpFractionalScaleV1 as Proxy>::Event;
+
+        match event {
+            Event::PreferredScale { scale } => {
+                let scale = Scale(scale);
+
+                if state.scale != scale {
+                    state.scale = scale;
+                    state.dirty = true;
+                }
             }
 
             _ => unreachable!(),
@@ -789,9 +3901,12 @@ impl Dispatch<WlBuffer, ()> for Window {
     }
 }
 
+// `WpViewport` has no events
+wayland_client::delegate_noop!(Window: WpViewport);
+
 impl Dispatch<ZwlrLayerSurfaceV1, ()> for Window {
     fn event(
-        _: &mut Self,
+        state: &mut Self,
         layer_surface: &ZwlrLayerSurfaceV1,
         event: <ZwlrLayerSurfaceV1 as Proxy>::Event,
         (): &(),
@@ -801,13 +3916,578 @@ impl Dispatch<ZwlrLayerSurfaceV1, ()> for Window {
         type Event = <ZwlrLayerSurfaceV1 as Proxy>::Event;
 
         match event {
-            Event::Configure { serial, .. } => {
+            Event::Configure {
+                serial,
+                width,
+                height,
+            } => {
                 layer_surface.ack_configure(serial);
+
+                // zero means that the size is left to the client
+                let width = match width {
+                    0 => state.requested.width,
+                    w => w as usize,
+                };
+
+                let height = match height {
+                    0 => state.requested.height,
+                    h => h as usize,
+                };
+
+                state.configured = Some(Size { width, height });
+                state.dirty = true;
             }
 
-            Event::Closed => unimplemented!(),
+            // e.g. the output is turned off, the window is destroyed by `ReadyGateState::reap`
+            Event::Closed => state.closed = true,
 
             _ => unreachable!(),
         }
     }
 }
+
+// --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- --- ---
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a `class/power_supply` tree under the temp dir, removed on drop
+    struct Sysfs(PathBuf);
+
+    impl Sysfs {
+        fn new(name: &str) -> Self {
+            let pid = std::process::id();
+            let root = std::env::temp_dir().join(format!("bananar-{pid}-{name}"));
+            let _ = std::fs::remove_dir_all(&root);
+
+            Self(root)
+        }
+
+        fn supply(&self, name: &str, attrs: &[(&str, &str)]) -> &Self {
+            let dir = self.0.join("class/power_supply").join(name);
+            std::fs::create_dir_all(&dir).unwrap();
+
+            for (attr, value) in attrs {
+                std::fs::write(dir.join(attr), format!("{value}\n")).unwrap();
+            }
+
+            self
+        }
+
+        fn monitor(&self) -> BatteryMonitor {
+            BatteryMonitor::new(&BatteryConfig {
+                sysfs: self.0.clone(),
+                ..Default::default()
+            })
+        }
+    }
+
+    impl Drop for Sysfs {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn batteries_of_the_system_only() {
+        let sysfs = Sysfs::new("batteries");
+        sysfs
+            .supply("BAT1", &[("type", "Battery"), ("capacity", "50")])
+            .supply("BAT0", &[("type", "Battery"), ("capacity", "80")])
+            .supply("AC", &[("type", "Mains"), ("online", "1")])
+            .supply(
+                "hidpp_battery_0",
+                &[("type", "Battery"), ("scope", "Device"), ("capacity", "30")],
+            )
+            .supply("ucsi-source-psy-USBC000:001", &[("type", "USB")]);
+
+        let batteries = read_batteries(&sysfs.0);
+        let names = batteries
+            .iter()
+            .map(|b| b.name.as_str())
+            .collect::<Vec<_>>();
+
+        assert_eq!(names, ["BAT0", "BAT1"]);
+        assert_eq!(batteries[0].capacity, 80.0);
+    }
+
+    #[test]
+    fn no_power_supply() {
+        let sysfs = Sysfs::new("empty");
+
+        assert!(read_batteries(&sysfs.0).is_empty());
+        assert!(read_adapters(&sysfs.0).is_empty());
+    }
+
+    #[test]
+    fn charge_into_energy() {
+        let sysfs = Sysfs::new("charge");
+        sysfs.supply(
+            "BAT0",
+            &[
+                ("type", "Battery"),
+                ("capacity", "50"),
+                ("charge_now", "2000000"),
+                ("charge_full", "4000000"),
+                ("voltage_now", "12000000"),
+                ("current_now", "-1500000"),
+            ],
+        );
+
+        let battery = &read_batteries(&sysfs.0)[0];
+
+        assert_eq!(battery.energy, Some(24.0));
+        assert_eq!(battery.energy_full, Some(48.0));
+        assert_eq!(battery.power, Some(18.0));
+    }
+
+    #[test]
+    fn energy_over_charge() {
+        let sysfs = Sysfs::new("energy");
+        sysfs.supply(
+            "BAT0",
+            &[
+                ("type", "Battery"),
+                ("capacity", "50"),
+                ("energy_now", "30000000"),
+                ("charge_now", "2000000"),
+                ("voltage_now", "12000000"),
+                ("power_now", "9000000"),
+            ],
+        );
+
+        let battery = &read_batteries(&sysfs.0)[0];
+
+        assert_eq!(battery.energy, Some(30.0));
+        assert_eq!(battery.energy_full, None);
+        assert_eq!(battery.power, Some(9.0));
+    }
+
+    #[test]
+    fn combined_weighted_by_full() {
+        let sysfs = Sysfs::new("weighted");
+        sysfs
+            .supply(
+                "BAT0",
+                &[
+                    ("type", "Battery"),
+                    ("status", "Full"),
+                    ("capacity", "100"),
+                    ("energy_full", "60000000"),
+                ],
+            )
+            .supply(
+                "BAT1",
+                &[
+                    ("type", "Battery"),
+                    ("status", "Not charging"),
+                    ("capacity", "40"),
+                    ("energy_full", "20000000"),
+                ],
+            );
+
+        let state = sysfs.monitor().read();
+
+        assert_eq!(state.combined, "85%");
+        assert_eq!(state.each, ["BAT0 100%", "BAT1 40%"]);
+        assert_eq!(state.status, Status::NotCharging);
+    }
+
+    #[test]
+    fn combined_averaged_without_full() {
+        let sysfs = Sysfs::new("averaged");
+        sysfs
+            .supply(
+                "BAT0",
+                &[
+                    ("type", "Battery"),
+                    ("capacity", "100"),
+                    ("energy_full", "60000000"),
+                ],
+            )
+            .supply("BAT1", &[("type", "Battery"), ("capacity", "40")]);
+
+        assert_eq!(sysfs.monitor().read().combined, "70%");
+    }
+
+    fn discharging(power: &str) -> [(&str, &str); 6] {
+        [
+            ("type", "Battery"),
+            ("status", "Discharging"),
+            ("capacity", "50"),
+            ("energy_now", "30000000"),
+            ("energy_full", "60000000"),
+            ("power_now", power),
+        ]
+    }
+
+    #[test]
+    fn eta_until_empty() {
+        let sysfs = Sysfs::new("empty-eta");
+        sysfs.supply("BAT0", &discharging("10000000"));
+
+        let mut monitor = sysfs.monitor();
+        let state = monitor.read();
+        assert_eq!(state.eta, "3:00");
+        assert_eq!(state.power, "10.0W");
+
+        // 10 + 0.3 * (20 - 10) = 13 watts
+        sysfs.supply("BAT0", &discharging("20000000"));
+
+        let state = monitor.read();
+        assert_eq!(state.eta, "2:18");
+        assert_eq!(state.power, "20.0W");
+    }
+
+    #[test]
+    fn eta_until_full() {
+        let sysfs = Sysfs::new("full-eta");
+        sysfs.supply("BAT0", &discharging("10000000"));
+
+        let mut monitor = sysfs.monitor();
+        assert_eq!(monitor.read().eta, "3:00");
+
+        // not smoothed from 10 watts of discharging
+        sysfs.supply("BAT0", &[("status", "Charging"), ("power_now", "30000000")]);
+
+        let state = monitor.read();
+        assert_eq!(state.status, Status::Charging);
+        assert_eq!(state.eta, "1:00");
+    }
+
+    #[test]
+    fn eta_unknown() {
+        let sysfs = Sysfs::new("unknown-eta");
+        sysfs.supply("BAT0", &discharging("0"));
+
+        let mut monitor = sysfs.monitor();
+        assert_eq!(monitor.read().eta, "");
+
+        sysfs.supply("BAT0", &[("status", "Full"), ("power_now", "10000000")]);
+        assert_eq!(monitor.read().eta, "");
+
+        sysfs.supply("BAT0", &[("status", "Not charging")]);
+        assert_eq!(monitor.read().eta, "");
+    }
+
+    #[test]
+    fn adapters_of_mains_and_usb() {
+        let sysfs = Sysfs::new("adapters");
+        sysfs
+            .supply("AC", &[("type", "Mains"), ("online", "0")])
+            .supply(
+                "ucsi-source-psy-USBC000:001",
+                &[("type", "USB_PD"), ("online", "1")],
+            )
+            .supply("BAT0", &[("type", "Battery"), ("capacity", "50")]);
+
+        let mut online = read_adapters(&sysfs.0)
+            .iter()
+            .map(|a| a.online)
+            .collect::<Vec<_>>();
+        online.sort();
+
+        assert_eq!(online, [false, true]);
+        assert!(sysfs.monitor().read().ac_online);
+    }
+
+    #[test]
+    fn adapters_offline() {
+        let sysfs = Sysfs::new("offline");
+        sysfs
+            .supply("AC", &[("type", "Mains"), ("online", "0")])
+            .supply("BAT0", &[("type", "Battery"), ("capacity", "50")]);
+
+        assert!(!sysfs.monitor().read().ac_online);
+    }
+
+    fn invalid_key(text: &str) -> &'static str {
+        match Config::parse(text) {
+            Err(ConfigError::Invalid { key, .. }) => key,
+            v => panic!("not invalid: {v:?}"),
+        }
+    }
+
+    #[test]
+    fn config_defaults() {
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+        assert_eq!(
+            Config::parse("[placement]\n[battery]\n").unwrap(),
+            Config::default()
+        );
+    }
+
+    #[test]
+    fn config_overrides() {
+        let config = Config::parse(
+            r##"
+            [placement]
+            edge = "left"
+            thickness = 32
+
+            [style]
+            foreground = "#ff8000"
+
+            [modules]
+            left = []
+            right = ["clock", "battery"]
+
+            [battery]
+            warning = 30
+            "##,
+        )
+        .unwrap();
+
+        assert_eq!(config.placement.edge, Edge::Left);
+        assert_eq!(config.placement.thickness, Some(32));
+        assert_eq!(
+            config.style.foreground,
+            slint::Color::from_rgb_u8(0xff, 0x80, 0)
+        );
+        assert!(config.modules.left.is_empty());
+        assert_eq!(
+            config.modules.right,
+            [ModuleKind::Clock, ModuleKind::Battery]
+        );
+        assert_eq!(config.battery.alert.warning, 30);
+        assert_eq!(
+            config.battery.alert.critical,
+            AlertConfig::default().critical
+        );
+    }
+
+    #[test]
+    fn config_unknown_keys() {
+        assert!(matches!(
+            Config::parse("bar = 1"),
+            Err(ConfigError::Syntax(_))
+        ));
+        assert!(matches!(
+            Config::parse("[placement]\nlayers = \"top\""),
+            Err(ConfigError::Syntax(_))
+        ));
+        assert!(matches!(
+            Config::parse("[battery]\nwarning = \"20\""),
+            Err(ConfigError::Syntax(_))
+        ));
+    }
+
+    #[test]
+    fn config_invalid_values() {
+        assert_eq!(
+            invalid_key("[placement]\nlayer = \"middle\""),
+            "placement.layer"
+        );
+        assert_eq!(
+            invalid_key("[placement]\nedge = \"center\""),
+            "placement.edge"
+        );
+        assert_eq!(
+            invalid_key("[placement]\nthickness = 0"),
+            "placement.thickness"
+        );
+        assert_eq!(
+            invalid_key("[placement]\nnamespace = \"\""),
+            "placement.namespace"
+        );
+        assert_eq!(invalid_key("[style]\nfont-weight = 0"), "style.font-weight");
+        assert_eq!(
+            invalid_key("[style]\nforeground = \"white\""),
+            "style.foreground"
+        );
+        assert_eq!(
+            invalid_key("[style]\nbackground = \"#fff\""),
+            "style.background"
+        );
+        assert_eq!(
+            invalid_key("[modules]\ncenter = [\"cpu\"]"),
+            "modules.center"
+        );
+        assert_eq!(invalid_key("[battery]\ninterval = 0"), "battery.interval");
+        assert_eq!(invalid_key("[battery]\nwarning = 101"), "battery.warning");
+        assert_eq!(
+            invalid_key("[battery]\nwarning = 10\ncritical = 20"),
+            "battery.critical"
+        );
+        assert_eq!(invalid_key("[clock]\nformat = \"%Q\""), "clock.format");
+        assert_eq!(
+            invalid_key("[clock]\nzones = [\"Nowhere/Atlantis\"]"),
+            "clock.zones"
+        );
+    }
+
+    #[test]
+    fn alert_hysteresis_within_thresholds() {
+        let config = "[battery]\nwarning = 20\ncritical = 10\nhysteresis = ";
+
+        assert_eq!(invalid_key(&format!("{config}15")), "battery.hysteresis");
+        assert_eq!(
+            Config::parse(&format!("{config}10"))
+                .unwrap()
+                .battery
+                .alert
+                .hysteresis,
+            10
+        );
+    }
+
+    fn alerts(from: Alert, levels: &[f32]) -> Vec<Alert> {
+        let config = AlertConfig::default();
+
+        levels
+            .iter()
+            .scan(from, |alert, &level| {
+                *alert = alert.next(level, &config);
+                Some(*alert)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn alert_at_warning() {
+        use Alert::*;
+
+        // entered at 20, held until above 20 + 3
+        assert_eq!(
+            alerts(Normal, &[25.0, 21.0, 20.0, 22.0, 23.0, 23.5, 21.0]),
+            [Normal, Normal, Warning, Warning, Warning, Normal, Normal]
+        );
+    }
+
+    #[test]
+    fn alert_at_critical() {
+        use Alert::*;
+
+        // entered at 10, held until above 10 + 3
+        assert_eq!(
+            alerts(Warning, &[11.0, 10.0, 12.0, 13.0, 13.5, 11.0, 10.0]),
+            [Warning, Critical, Critical, Critical, Warning, Warning, Critical]
+        );
+    }
+
+    #[test]
+    fn alert_resumed_on_reloading() {
+        let sysfs = Sysfs::new("resumed");
+        sysfs.supply(
+            "BAT0",
+            &[
+                ("type", "Battery"),
+                ("status", "Discharging"),
+                ("capacity", "15"),
+            ],
+        );
+
+        let config = BatteryConfig {
+            sysfs: sysfs.0.clone(),
+            alert: AlertConfig {
+                warning_command: Some("true".into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let mut module = BatteryModule::new(&config);
+        let Some(last) = module.poll(Trigger::Start).unwrap() else {
+            panic!("nothing is read");
+        };
+        assert_eq!(module.monitor.hooks.len(), 1);
+
+        let mut module = BatteryModule::new(&config);
+        module.resume(&last);
+        module.poll(Trigger::Start).unwrap();
+        assert!(module.monitor.hooks.is_empty());
+        assert_eq!(module.monitor.alert, Alert::Warning);
+    }
+
+    #[test]
+    fn battery_segment_state() {
+        let state = |alert, status, ac_online| {
+            let battery = BatteryState {
+                status,
+                alert,
+                ac_online,
+                ..Default::default()
+            };
+
+            Output::Battery(battery).segment().state
+        };
+
+        assert_eq!(state(Alert::Normal, Status::Discharging, false), "normal");
+        assert_eq!(state(Alert::Normal, Status::NotCharging, true), "charging");
+        assert_eq!(state(Alert::Normal, Status::Charging, false), "charging");
+        assert_eq!(
+            state(Alert::Critical, Status::Discharging, true),
+            "critical"
+        );
+    }
+
+    #[test]
+    fn alert_skipping_thresholds() {
+        use Alert::*;
+
+        assert_eq!(alerts(Normal, &[5.0]), [Critical]);
+        assert_eq!(alerts(Critical, &[50.0]), [Normal]);
+        assert_eq!(alerts(Critical, &[22.0]), [Warning]);
+    }
+
+    fn clock(format: &str) -> ClockModule {
+        let config = ClockConfig {
+            format: format.into(),
+            ..Default::default()
+        };
+
+        ClockModule::new(&config).unwrap()
+    }
+
+    #[test]
+    fn clock_zone_designations() {
+        let jst = tz::TimeZone::from_posix_tz("JST-9").unwrap();
+        let utc = tz::TimeZone::from_posix_tz("UTC0").unwrap();
+        let now = Duration::from_secs(86400 + 3600 + 120 + 3);
+
+        assert_eq!(clock("%H:%M %Z").format_in(&jst, now).unwrap(), "10:02 JST");
+        assert_eq!(clock("%T %Z").format_in(&utc, now).unwrap(), "01:02:03 UTC");
+        assert_eq!(
+            clock("%Y-%m-%d").format_in(&jst, now).unwrap(),
+            "1970-01-02"
+        );
+
+        // escaped ones are left as is
+        assert_eq!(clock("%%Z").format_in(&jst, now).unwrap(), "%Z");
+        assert_eq!(clock("%%%Z").format_in(&jst, now).unwrap(), "%JST");
+    }
+
+    #[test]
+    fn clock_seconds() {
+        assert!(shows_seconds("%T"));
+        assert!(shows_seconds("%H:%M:%S"));
+        assert!(shows_seconds("%s"));
+        assert!(shows_seconds("%H:%M:%S%.3f"));
+
+        assert!(!shows_seconds("%H:%M"));
+        assert!(!shows_seconds("%a %d %b %R"));
+        assert!(!shows_seconds("%%S"));
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(
+            parse_color("#102030"),
+            Some(slint::Color::from_rgb_u8(0x10, 0x20, 0x30))
+        );
+        assert_eq!(
+            parse_color("#10203040"),
+            Some(slint::Color::from_argb_u8(0x40, 0x10, 0x20, 0x30))
+        );
+        assert_eq!(
+            parse_color("#ABCDEF"),
+            Some(slint::Color::from_rgb_u8(0xab, 0xcd, 0xef))
+        );
+
+        assert_eq!(parse_color("102030"), None);
+        assert_eq!(parse_color("#1020"), None);
+        assert_eq!(parse_color("#10203g"), None);
+        assert_eq!(parse_color("#+10203"), None);
+    }
+}